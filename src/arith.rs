@@ -0,0 +1,564 @@
+use crate::expand::ExpandError;
+use crate::vars::Variables;
+
+/// How deeply variables whose values are themselves expressions may nest.
+/// Each level takes a full descent through the parser, so this stays well
+/// within a thread's stack rather than matching bash's 1024.
+const MAX_RECURSION: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(i64),
+    Name(String),
+    Op(&'static str),
+    Eof,
+}
+
+/// Operators, longest first so that e.g. `<<=` wins over `<<` and `<`.
+const OPS: [&str; 36] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "=", "?", ":",
+];
+
+const ASSIGN_OPS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+/// Evaluates an already-expanded arithmetic expression, as in `$((expr))`.
+///
+/// Integers are 64-bit and wrap on overflow. Names refer to shell
+/// variables; their values are evaluated as expressions in turn, and unset
+/// or empty variables count as 0.
+pub fn eval(expr: &str, vars: &mut Variables) -> Result<i64, ExpandError> {
+    eval_nested(expr, vars, 0)
+}
+
+fn eval_nested(expr: &str, vars: &mut Variables, depth: usize) -> Result<i64, ExpandError> {
+    let mut parser = Parser {
+        expr,
+        tokens: tokenize(expr)?,
+        pos: 0,
+        vars,
+        noeval: 0,
+        depth,
+    };
+    if parser.peek() == &Tok::Eof {
+        return Ok(0);
+    }
+    let value = parser.comma()?;
+    if parser.peek() != &Tok::Eof {
+        return Err(parser.error("syntax error in expression"));
+    }
+    Ok(value)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(Tok, usize)>, ExpandError> {
+    let mut tokens = Vec::new();
+    let bytes = expr.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        if c.is_ascii_digit() {
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'#' | b'@' | b'_'))
+            {
+                i += 1;
+            }
+            let value = parse_number(&expr[start..i])
+                .ok_or_else(|| error(expr, &expr[start..], "value too great for base"))?;
+            tokens.push((Tok::Num(value), start));
+        } else if c == '_' || c.is_ascii_alphabetic() {
+            while i < bytes.len() && (bytes[i] == b'_' || bytes[i].is_ascii_alphanumeric()) {
+                i += 1;
+            }
+            tokens.push((Tok::Name(expr[start..i].to_string()), start));
+        } else if c == '(' || c == ')' || c == ',' {
+            let op = match c {
+                '(' => "(",
+                ')' => ")",
+                _ => ",",
+            };
+            tokens.push((Tok::Op(op), start));
+            i += 1;
+        } else {
+            let op = OPS
+                .iter()
+                .find(|op| expr[i..].starts_with(*op))
+                .ok_or_else(|| {
+                    error(
+                        expr,
+                        &expr[i..],
+                        "syntax error: invalid arithmetic operator",
+                    )
+                })?;
+            tokens.push((Tok::Op(op), start));
+            i += op.len();
+        }
+    }
+    tokens.push((Tok::Eof, expr.len()));
+    Ok(tokens)
+}
+
+/// Parses a decimal, octal (`017`), hex (`0x1f`) or `base#digits` constant.
+fn parse_number(text: &str) -> Option<i64> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        (
+            base.parse::<u32>().ok().filter(|b| (2..=64).contains(b))?,
+            digits,
+        )
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            // Upper case letters are the same digits as lower case up to base 36
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return None,
+        };
+        if digit >= base {
+            return None;
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Some(value)
+}
+
+fn error(expr: &str, rest: &str, message: &str) -> ExpandError {
+    ExpandError(format!(
+        "{}: {} (error token is \"{}\")",
+        expr,
+        message,
+        rest.trim()
+    ))
+}
+
+struct Parser<'a> {
+    expr: &'a str,
+    tokens: Vec<(Tok, usize)>,
+    pos: usize,
+    vars: &'a mut Variables,
+    /// Non-zero while parsing an operand that short-circuiting skips, whose
+    /// assignments and errors must not take effect.
+    noeval: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Tok::Op(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].0.clone();
+        if tok != Tok::Eof {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn error(&self, message: &str) -> ExpandError {
+        error(self.expr, &self.expr[self.tokens[self.pos].1..], message)
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ExpandError> {
+        if self.peek_op() != Some(op) {
+            return Err(self.error(&format!("syntax error: `{}' expected", op)));
+        }
+        self.next();
+        Ok(())
+    }
+
+    /// Splits a `++` or `--` token into two single-character operators, for
+    /// when it cannot be an increment, as in `5--3`.
+    fn split_double(&mut self) {
+        if let Some(op @ ("++" | "--")) = self.peek_op() {
+            let single = &op[..1];
+            let start = self.tokens[self.pos].1;
+            self.tokens[self.pos].0 = Tok::Op(single);
+            self.tokens
+                .insert(self.pos + 1, (Tok::Op(single), start + 1));
+        }
+    }
+
+    fn comma(&mut self) -> Result<i64, ExpandError> {
+        let mut value = self.assignment()?;
+        while self.peek_op() == Some(",") {
+            self.next();
+            value = self.assignment()?;
+        }
+        Ok(value)
+    }
+
+    fn assignment(&mut self) -> Result<i64, ExpandError> {
+        let is_assignment = matches!(self.peek(), Tok::Name(_))
+            && matches!(&self.tokens[self.pos + 1].0, Tok::Op(op) if ASSIGN_OPS.contains(op));
+        if !is_assignment {
+            return self.ternary();
+        }
+
+        let Tok::Name(name) = self.next() else {
+            unreachable!()
+        };
+        let Tok::Op(op) = self.next() else {
+            unreachable!()
+        };
+        let rhs_pos = self.pos;
+        let rhs = self.assignment()?;
+        if self.noeval > 0 {
+            return Ok(rhs);
+        }
+        let value = if op == "=" {
+            rhs
+        } else {
+            let current = self.variable(&name)?;
+            let binary = &op[..op.len() - 1];
+            self.binary(binary, current, rhs, rhs_pos)?
+        };
+        self.vars.set(&name, value.to_string());
+        Ok(value)
+    }
+
+    fn ternary(&mut self) -> Result<i64, ExpandError> {
+        let condition = self.logical_or()?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.next();
+
+        if condition == 0 {
+            self.noeval += 1;
+        }
+        let then = self.comma();
+        if condition == 0 {
+            self.noeval -= 1;
+        }
+        let then = then?;
+        self.expect(":")?;
+        if condition != 0 {
+            self.noeval += 1;
+        }
+        let otherwise = self.ternary();
+        if condition != 0 {
+            self.noeval -= 1;
+        }
+        let otherwise = otherwise?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn logical_or(&mut self) -> Result<i64, ExpandError> {
+        let mut value = self.logical_and()?;
+        while self.peek_op() == Some("||") {
+            self.next();
+            let skip = value != 0;
+            self.noeval += usize::from(skip);
+            let rhs = self.logical_and();
+            self.noeval -= usize::from(skip);
+            value = i64::from(value != 0 || rhs? != 0);
+        }
+        Ok(value)
+    }
+
+    fn logical_and(&mut self) -> Result<i64, ExpandError> {
+        let mut value = self.binary_level(0)?;
+        while self.peek_op() == Some("&&") {
+            self.next();
+            let skip = value == 0;
+            self.noeval += usize::from(skip);
+            let rhs = self.binary_level(0);
+            self.noeval -= usize::from(skip);
+            value = i64::from(value != 0 && rhs? != 0);
+        }
+        Ok(value)
+    }
+
+    /// Left-associative binary operators, from loosest to tightest binding.
+    fn binary_level(&mut self, level: usize) -> Result<i64, ExpandError> {
+        const LEVELS: [&[&str]; 8] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.power();
+        };
+
+        let mut value = self.binary_level(level + 1)?;
+        loop {
+            if level == 6 {
+                self.split_double();
+            }
+            let Some(op) = self.peek_op().filter(|op| ops.contains(op)) else {
+                return Ok(value);
+            };
+            self.next();
+            let rhs_pos = self.pos;
+            let rhs = self.binary_level(level + 1)?;
+            value = self.binary(op, value, rhs, rhs_pos)?;
+        }
+    }
+
+    fn power(&mut self) -> Result<i64, ExpandError> {
+        let base = self.unary()?;
+        if self.peek_op() != Some("**") {
+            return Ok(base);
+        }
+        self.next();
+        let rhs_pos = self.pos;
+        // Right-associative: 2**3**2 is 2**9
+        let exponent = self.power()?;
+        self.binary("**", base, exponent, rhs_pos)
+    }
+
+    fn unary(&mut self) -> Result<i64, ExpandError> {
+        if let Some(op @ ("++" | "--")) = self.peek_op() {
+            if !matches!(self.tokens[self.pos + 1].0, Tok::Name(_)) {
+                self.split_double();
+                return self.unary();
+            }
+            self.next();
+            let Tok::Name(name) = self.next() else {
+                unreachable!()
+            };
+            let value = self.variable(&name)?;
+            let value = if op == "++" {
+                value.wrapping_add(1)
+            } else {
+                value.wrapping_sub(1)
+            };
+            if self.noeval == 0 {
+                self.vars.set(&name, value.to_string());
+            }
+            return Ok(value);
+        }
+
+        match self.peek_op() {
+            Some("-") => {
+                self.next();
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some("+") => {
+                self.next();
+                self.unary()
+            }
+            Some("!") => {
+                self.next();
+                Ok(i64::from(self.unary()? == 0))
+            }
+            Some("~") => {
+                self.next();
+                Ok(!self.unary()?)
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<i64, ExpandError> {
+        match self.next() {
+            Tok::Num(value) => Ok(value),
+            Tok::Name(name) => {
+                let value = self.variable(&name)?;
+                if let Some(op @ ("++" | "--")) = self.peek_op() {
+                    self.next();
+                    let updated = if op == "++" {
+                        value.wrapping_add(1)
+                    } else {
+                        value.wrapping_sub(1)
+                    };
+                    if self.noeval == 0 {
+                        self.vars.set(&name, updated.to_string());
+                    }
+                }
+                Ok(value)
+            }
+            Tok::Op("(") => {
+                let value = self.comma()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            _ => {
+                self.pos = self.pos.saturating_sub(1);
+                Err(self.error("syntax error: operand expected"))
+            }
+        }
+    }
+
+    /// The value of a variable, evaluating its text as an expression.
+    fn variable(&mut self, name: &str) -> Result<i64, ExpandError> {
+        let text = self.vars.get(name).unwrap_or_default().trim().to_string();
+        if text.is_empty() || self.noeval > 0 {
+            return Ok(0);
+        }
+        if let Ok(value) = text.parse() {
+            return Ok(value);
+        }
+        if self.depth >= MAX_RECURSION {
+            return Err(ExpandError(format!(
+                "{}: expression recursion level exceeded (error token is \"{}\")",
+                name, name
+            )));
+        }
+        eval_nested(&text, self.vars, self.depth + 1)
+    }
+
+    /// Applies a binary operator; `rhs_pos` locates the right operand for
+    /// error messages.
+    fn binary(&self, op: &str, lhs: i64, rhs: i64, rhs_pos: usize) -> Result<i64, ExpandError> {
+        let rhs_error =
+            |message: &str| error(self.expr, &self.expr[self.tokens[rhs_pos].1..], message);
+        Ok(match op {
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" | "%" if rhs == 0 => {
+                if self.noeval > 0 {
+                    return Ok(0);
+                }
+                return Err(rhs_error("division by 0"));
+            }
+            "/" => lhs.wrapping_div(rhs),
+            "%" => lhs.wrapping_rem(rhs),
+            "**" if rhs < 0 => {
+                if self.noeval > 0 {
+                    return Ok(0);
+                }
+                return Err(rhs_error("exponent less than 0"));
+            }
+            "**" => lhs.wrapping_pow(u32::try_from(rhs).unwrap_or(u32::MAX)),
+            "<<" => lhs.wrapping_shl(rhs as u32),
+            ">>" => lhs.wrapping_shr(rhs as u32),
+            "<" => i64::from(lhs < rhs),
+            ">" => i64::from(lhs > rhs),
+            "<=" => i64::from(lhs <= rhs),
+            ">=" => i64::from(lhs >= rhs),
+            "==" => i64::from(lhs == rhs),
+            "!=" => i64::from(lhs != rhs),
+            "&" => lhs & rhs,
+            "^" => lhs ^ rhs,
+            "|" => lhs | rhs,
+            _ => unreachable!("not a binary operator: {}", op),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_str(expr: &str) -> Result<i64, String> {
+        eval(expr, &mut Variables::default()).map_err(|err| err.0)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval_str("2 + 3 * 4"), Ok(14));
+        assert_eq!(eval_str("2 * (3 + 4)"), Ok(14));
+        assert_eq!(eval_str("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval_str("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval_str("-2 ** 2"), Ok(4));
+        assert_eq!(eval_str("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval_str("7 & 3 == 3"), Ok(1));
+        assert_eq!(eval_str("1 < 2 == 1"), Ok(1));
+        assert_eq!(eval_str("5 % 3 * 2"), Ok(4));
+        assert_eq!(eval_str("1 ? 2 : 3 ? 4 : 5"), Ok(2));
+        assert_eq!(eval_str("!0 + ~0"), Ok(0));
+    }
+
+    #[test]
+    fn division_truncates_toward_zero() {
+        assert_eq!(eval_str("-7 / 2"), Ok(-3));
+        assert_eq!(eval_str("-7 % 3"), Ok(-1));
+    }
+
+    #[test]
+    fn number_bases() {
+        assert_eq!(eval_str("0x1f"), Ok(31));
+        assert_eq!(eval_str("010"), Ok(8));
+        assert_eq!(eval_str("2#101"), Ok(5));
+    }
+
+    #[test]
+    fn empty_expression_is_zero() {
+        assert_eq!(eval_str(""), Ok(0));
+        assert_eq!(eval_str("  "), Ok(0));
+    }
+
+    #[test]
+    fn variables_and_assignment() {
+        let mut vars = Variables::default();
+        assert_eq!(eval("x = 5, x += 2, x * 2", &mut vars).unwrap(), 14);
+        assert_eq!(vars.get("x"), Some("7"));
+        assert_eq!(eval("x++ + ++x", &mut vars).unwrap(), 16);
+        assert_eq!(vars.get("x"), Some("9"));
+
+        // Values are expressions in their own right, and unset names are 0
+        vars.set("y", "x + 1".to_string());
+        assert_eq!(eval("y * 2 + unset", &mut vars).unwrap(), 20);
+    }
+
+    #[test]
+    fn short_circuit_skips_side_effects_and_errors() {
+        let mut vars = Variables::default();
+        assert_eq!(eval("0 && (z = 1)", &mut vars).unwrap(), 0);
+        assert_eq!(eval("1 || (z = 1)", &mut vars).unwrap(), 1);
+        assert_eq!(eval("1 ? 2 : (z = 1)", &mut vars).unwrap(), 2);
+        assert_eq!(vars.get("z"), None);
+        assert_eq!(eval("0 && 1/0", &mut vars).unwrap(), 0);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            eval_str("1/0"),
+            Err(r#"1/0: division by 0 (error token is "0")"#.to_string())
+        );
+        assert!(eval_str("5 % 0").unwrap_err().contains("division by 0"));
+        assert!(eval_str("2 ** -1")
+            .unwrap_err()
+            .contains("exponent less than 0"));
+        assert!(eval_str("1 +").unwrap_err().contains("operand expected"));
+        assert!(eval_str("1 2")
+            .unwrap_err()
+            .contains("syntax error in expression"));
+        assert!(eval_str("(1 + 2").is_err());
+        assert!(eval_str("3 = 4").is_err());
+    }
+
+    #[test]
+    fn self_referencing_variable_is_an_error() {
+        let mut vars = Variables::default();
+        vars.set("loop", "loop + 1".to_string());
+        assert!(eval("loop", &mut vars).is_err());
+    }
+}
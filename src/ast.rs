@@ -0,0 +1,331 @@
+use std::rc::Rc;
+
+use crate::vars::is_valid_name;
+
+/// Byte range of a node in the source line it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Unquoted text.
+    Literal(String),
+    /// Text from single quotes or a backslash escape, taken verbatim.
+    Quoted(String),
+    /// The contents of a double-quoted string.
+    DoubleQuoted(Vec<WordPart>),
+    /// A parameter expansion such as `$HOME`, `${HOME:-/}` or `$?`.
+    Param(ParamExpansion),
+    /// `$(list)` or `` `list` ``.
+    CommandSub(List),
+    /// `$((expression))`, expanded like double-quoted text before evaluation.
+    Arithmetic(Word),
+    /// `<(list)`, or `>(list)` when `output` is set: a `/dev/fd` path to a
+    /// pipe from the list's output or into its input.
+    ProcessSub { list: List, output: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamExpansion {
+    pub name: String,
+    pub op: ParamOp,
+}
+
+/// The operator inside `${...}`. `colon` records whether the `:` form was
+/// used, which treats an empty value the same as an unset one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamOp {
+    /// `$v` or `${v}`
+    Plain,
+    /// `${#v}`
+    Length,
+    /// `${v:-word}`
+    Default { colon: bool, word: Word },
+    /// `${v:=word}`
+    Assign { colon: bool, word: Word },
+    /// `${v:?word}`
+    Error { colon: bool, word: Word },
+    /// `${v:+word}`
+    Alternative { colon: bool, word: Word },
+    /// `${v#pat}` and `${v##pat}`
+    RemovePrefix { longest: bool, pattern: Word },
+    /// `${v%pat}` and `${v%%pat}`
+    RemoveSuffix { longest: bool, pattern: Word },
+    /// `${v/pat/rep}`, `${v//pat/rep}`, `${v/#pat/rep}` and `${v/%pat/rep}`
+    Replace {
+        mode: ReplaceMode,
+        pattern: Word,
+        replacement: Word,
+    },
+    /// `${v:offset}` and `${v:offset:length}`
+    Substring { offset: Word, length: Option<Word> },
+    /// `${v^}`, `${v^^}`, `${v,}` and `${v,,}`, with an optional pattern
+    /// restricting which characters change.
+    Case {
+        upper: bool,
+        all: bool,
+        pattern: Option<Word>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaceMode {
+    First,
+    All,
+    Prefix,
+    Suffix,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+impl Word {
+    /// The word's text after quote removal.
+    pub fn unquoted(&self) -> String {
+        fn push_parts(parts: &[WordPart], out: &mut String) {
+            for part in parts {
+                match part {
+                    WordPart::Literal(s) | WordPart::Quoted(s) => out.push_str(s),
+                    WordPart::DoubleQuoted(inner) => push_parts(inner, out),
+                    WordPart::Param(param) => {
+                        out.push('$');
+                        out.push_str(&param.name);
+                    }
+                    WordPart::CommandSub(_) => out.push_str("$(...)"),
+                    WordPart::Arithmetic(_) => out.push_str("$((...))"),
+                    WordPart::ProcessSub { output: false, .. } => out.push_str("<(...)"),
+                    WordPart::ProcessSub { output: true, .. } => out.push_str(">(...)"),
+                }
+            }
+        }
+        let mut out = String::new();
+        push_parts(&self.parts, &mut out);
+        out
+    }
+
+    /// Splits a `NAME=value` word into its name and value.
+    pub fn as_assignment(&self) -> Option<Assignment> {
+        let Some(WordPart::Literal(first)) = self.parts.first() else {
+            return None;
+        };
+        let (name, value) = first.split_once('=')?;
+        if !is_valid_name(name) {
+            return None;
+        }
+
+        let mut parts = Vec::new();
+        if !value.is_empty() {
+            parts.push(WordPart::Literal(value.to_string()));
+        }
+        parts.extend(self.parts[1..].iter().cloned());
+        let value_start = self.span.start + name.len() + 1;
+        Some(Assignment {
+            name: name.to_string(),
+            value: Word {
+                parts,
+                span: Span::new(value_start, self.span.end),
+            },
+            span: self.span,
+        })
+    }
+
+    /// The word's text if it is a single unquoted literal, as reserved words must be.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(s)] => Some(s),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectType {
+    /// `[n]< file`
+    Input,
+    /// `[n]> file`, or `[n]>> file` when appending.
+    Output,
+    /// `[n]<> file`, opened for reading and writing.
+    ReadWrite,
+    /// `&> file` or `&>> file`, for both standard output and standard error.
+    OutputAndError,
+    /// `[n]<& word`, where the word is a descriptor number or `-` to close.
+    DuplicateInput,
+    /// `[n]>& word`. A word that is not a number or `-` makes this the same
+    /// as `&>`.
+    DuplicateOutput,
+    /// `[n]<< delimiter`, whose target is the body: a single `Quoted` part
+    /// if the delimiter was quoted, otherwise a `DoubleQuoted` one.
+    HereDoc,
+    /// `[n]<<< word`, whose target is the word.
+    HereString,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// The descriptor redirected, after defaulting to 0 or 1.
+    pub fd: u32,
+    /// For `{name}>file`, the variable that receives the number of a newly
+    /// allocated descriptor, used instead of `fd`.
+    pub fd_var: Option<String>,
+    pub redirect_type: RedirectType,
+    pub target: Word,
+    pub append: bool,
+    /// Written `>|`, to overwrite a file even with `set -o noclobber`.
+    pub clobber: bool,
+    pub span: Span,
+}
+
+/// A `NAME=value` word at the start of a simple command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    /// Line of the script the command starts on, for error messages.
+    pub line: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    /// `( list )`
+    Subshell(List),
+    /// `{ list; }`
+    BraceGroup(List),
+    /// `(( expression ))`
+    Arithmetic(Word),
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    If {
+        branches: Vec<(List, List)>,
+        else_body: Option<List>,
+    },
+    /// `while list; do list; done`
+    While { condition: List, body: List },
+    /// `until list; do list; done`
+    Until { condition: List, body: List },
+    /// `case word in [(]pattern[|pattern]...) list ;; ... esac`
+    Case { word: Word, items: Vec<CaseItem> },
+    /// `for name [in word...]; do list; done`. Without `in`, the words are
+    /// the positional parameters.
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+/// What happens after a `case` item's body runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseTerminator {
+    /// `;;`: the `case` is done.
+    Break,
+    /// `;&`: the next item's body runs too, without testing its patterns.
+    FallThrough,
+    /// `;;&`: matching goes on with the next item's patterns.
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound {
+        body: CompoundCommand,
+        redirects: Vec<Redirect>,
+        line: usize,
+        span: Span,
+    },
+    /// `name() compound-command` or `function name compound-command`. The
+    /// body is shared with the function table rather than copied into it.
+    FunctionDef {
+        name: String,
+        body: Rc<Command>,
+        span: Span,
+    },
+}
+
+impl Command {
+    pub fn span(&self) -> Span {
+        match self {
+            Command::Simple(simple) => simple.span,
+            Command::Compound { span, .. } | Command::FunctionDef { span, .. } => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    /// Written with a leading `!`, which inverts the exit status.
+    pub negated: bool,
+    pub span: Span,
+    /// The pipeline as written, to show for it as a job.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AndOrOp {
+    And,
+    Or,
+}
+
+/// A pipeline followed by any number of `&&` / `||` continuations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(AndOrOp, Pipeline)>,
+    pub span: Span,
+    /// Terminated by `&`, to run in the background.
+    pub background: bool,
+}
+
+impl AndOr {
+    /// The list as written, to show for it as a job.
+    pub fn text(&self) -> String {
+        let mut text = self.first.text.clone();
+        for (op, pipeline) in &self.rest {
+            text.push_str(match op {
+                AndOrOp::And => " && ",
+                AndOrOp::Or => " || ",
+            });
+            text.push_str(&pipeline.text);
+        }
+        text
+    }
+}
+
+/// A sequence of and-or lists separated by `;`, `&` or newlines.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct List {
+    pub items: Vec<AndOr>,
+}
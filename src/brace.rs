@@ -0,0 +1,260 @@
+use crate::ast::{Word, WordPart};
+
+/// One unit of a word as seen by brace expansion: an unquoted character,
+/// which may be brace syntax, or any other part, which never is.
+#[derive(Debug, Clone)]
+enum Item {
+    Char(char),
+    Part(WordPart),
+}
+
+/// Brace expansion: `a{b,c}d` becomes `abd acd`, and `{1..5}`, `{01..10}`,
+/// `{a..e}` and `{1..10..2}` become sequences. Braces that do not form a
+/// valid expression are left alone, as are braces inside quotes.
+pub fn expand(word: &Word) -> Vec<Word> {
+    let has_brace = word
+        .parts
+        .iter()
+        .any(|part| matches!(part, WordPart::Literal(text) if text.contains('{')));
+    if !has_brace {
+        return vec![word.clone()];
+    }
+
+    let mut items = Vec::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(text) => items.extend(text.chars().map(Item::Char)),
+            part => items.push(Item::Part(part.clone())),
+        }
+    }
+
+    let mut words = Vec::new();
+    expand_items(items, &mut words);
+    words
+        .into_iter()
+        .map(|items| Word {
+            parts: into_parts(items),
+            span: word.span,
+        })
+        .collect()
+}
+
+fn expand_items(items: Vec<Item>, out: &mut Vec<Vec<Item>>) {
+    let Some((start, end, alternatives)) = find_brace(&items) else {
+        out.push(items);
+        return;
+    };
+
+    let prefix = &items[..start];
+    let suffix = &items[end + 1..];
+    for alternative in alternatives {
+        let mut expanded = prefix.to_vec();
+        expanded.extend(alternative);
+        expanded.extend_from_slice(suffix);
+        expand_items(expanded, out);
+    }
+}
+
+/// Finds the first expandable brace expression, returning the positions of
+/// its braces and the alternatives it expands to.
+fn find_brace(items: &[Item]) -> Option<(usize, usize, Vec<Vec<Item>>)> {
+    for start in 0..items.len() {
+        if !is_char(&items[start], '{') {
+            continue;
+        }
+        // `${` is parameter syntax, never a brace expression
+        if start > 0 && is_char(&items[start - 1], '$') {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut end = None;
+        for (i, item) in items.iter().enumerate().skip(start + 1) {
+            match item {
+                Item::Char('{') => depth += 1,
+                Item::Char('}') if depth == 0 => {
+                    end = Some(i);
+                    break;
+                }
+                Item::Char('}') => depth -= 1,
+                Item::Char(',') if depth == 0 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            continue;
+        };
+
+        if !commas.is_empty() {
+            let mut alternatives = Vec::new();
+            let mut from = start + 1;
+            for to in commas.into_iter().chain([end]) {
+                alternatives.push(items[from..to].to_vec());
+                from = to + 1;
+            }
+            return Some((start, end, alternatives));
+        }
+        if let Some(sequence) = sequence(&items[start + 1..end]) {
+            let alternatives = sequence
+                .into_iter()
+                .map(|text| text.chars().map(Item::Char).collect())
+                .collect();
+            return Some((start, end, alternatives));
+        }
+    }
+    None
+}
+
+/// Expands the body of a `{x..y[..step]}` sequence, if it is one.
+fn sequence(items: &[Item]) -> Option<Vec<String>> {
+    let body = items
+        .iter()
+        .map(|item| match item {
+            Item::Char(c) => Some(*c),
+            Item::Part(_) => None,
+        })
+        .collect::<Option<String>>()?;
+
+    let fields: Vec<&str> = body.split("..").collect();
+    let (first, last, step) = match fields.as_slice() {
+        [first, last] => (*first, *last, None),
+        [first, last, step] => (*first, *last, Some(step.parse::<i64>().ok()?)),
+        _ => return None,
+    };
+    let step = step.map_or(1, |step: i64| step.unsigned_abs().max(1));
+
+    if let (Ok(from), Ok(to)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        let width = if is_zero_padded(first) || is_zero_padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        return Some(
+            range(from, to, step)
+                .into_iter()
+                .map(|n| pad(n, width))
+                .collect(),
+        );
+    }
+
+    let (from, to) = (single_char(first)?, single_char(last)?);
+    Some(
+        range(from as i64, to as i64, step)
+            .into_iter()
+            .filter_map(|n| char::from_u32(n as u32))
+            .map(String::from)
+            .collect(),
+    )
+}
+
+fn range(from: i64, to: i64, step: u64) -> Vec<i64> {
+    let step = step as usize;
+    if from <= to {
+        (from..=to).step_by(step).collect()
+    } else {
+        (to..=from).rev().step_by(step).collect()
+    }
+}
+
+fn is_zero_padded(number: &str) -> bool {
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+/// Formats `n` zero-padded to `width` characters, counting any minus sign.
+fn pad(n: i64, width: usize) -> String {
+    if n < 0 {
+        format!("-{:0>1$}", n.unsigned_abs(), width.saturating_sub(1))
+    } else {
+        format!("{:0>1$}", n, width)
+    }
+}
+
+fn single_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+fn is_char(item: &Item, c: char) -> bool {
+    matches!(item, Item::Char(ch) if *ch == c)
+}
+
+fn into_parts(items: Vec<Item>) -> Vec<WordPart> {
+    let mut parts = Vec::new();
+    for item in items {
+        match item {
+            Item::Char(c) => match parts.last_mut() {
+                Some(WordPart::Literal(text)) => text.push(c),
+                _ => parts.push(WordPart::Literal(c.to_string())),
+            },
+            Item::Part(part) => parts.push(part),
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_parts(parts: Vec<WordPart>) -> Vec<String> {
+        let word = Word {
+            parts,
+            ..Word::default()
+        };
+        expand(&word).iter().map(Word::unquoted).collect()
+    }
+
+    fn expand_str(text: &str) -> Vec<String> {
+        expand_parts(vec![WordPart::Literal(text.to_string())])
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(expand_str("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_str("{x,,y}"), ["x", "", "y"]);
+        assert_eq!(expand_str("a{b,c{d,e}}"), ["ab", "acd", "ace"]);
+        assert_eq!(expand_str("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand_str("{1..5}"), ["1", "2", "3", "4", "5"]);
+        assert_eq!(expand_str("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand_str("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(expand_str("{1..10..4}"), ["1", "5", "9"]);
+        assert_eq!(expand_str("{08..11}"), ["08", "09", "10", "11"]);
+        assert_eq!(expand_str("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(expand_str("{c..a}"), ["c", "b", "a"]);
+        assert_eq!(expand_str("x{1..2}y"), ["x1y", "x2y"]);
+    }
+
+    #[test]
+    fn invalid_braces_are_left_alone() {
+        assert_eq!(expand_str("{a}"), ["{a}"]);
+        assert_eq!(expand_str("{}"), ["{}"]);
+        assert_eq!(expand_str("{1..a}"), ["{1..a}"]);
+        assert_eq!(expand_str("{a,b"), ["{a,b"]);
+        assert_eq!(expand_str("plain"), ["plain"]);
+    }
+
+    #[test]
+    fn quoted_parts_are_not_brace_syntax() {
+        let parts = vec![
+            WordPart::Literal("x{a,b}".to_string()),
+            WordPart::Quoted("{c,d}".to_string()),
+        ];
+        assert_eq!(expand_parts(parts), ["xa{c,d}", "xb{c,d}"]);
+
+        let parts = vec![
+            WordPart::Literal("{a".to_string()),
+            WordPart::Quoted(",".to_string()),
+            WordPart::Literal("b}".to_string()),
+        ];
+        assert_eq!(expand_parts(parts), ["{a,b}"]);
+    }
+}
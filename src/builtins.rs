@@ -0,0 +1,1074 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
+use crate::jobs;
+use crate::options::{SetOptions, Shopts};
+use crate::shell::{self, Flow, Shell};
+use crate::trap::Condition;
+use crate::vars::is_valid_name;
+
+#[derive(Debug, PartialEq)]
+pub enum ShellCommand {
+    Exit,
+    Echo,
+    Type,
+    Pwd,
+    Cd,
+    Export,
+    Unset,
+    Shopt,
+    Set,
+    Break,
+    Continue,
+    Return,
+    Local,
+    Shift,
+    Source,
+    Eval,
+    Alias,
+    Unalias,
+    Jobs,
+    Fg,
+    Bg,
+    Wait,
+    Disown,
+    Kill,
+    Trap,
+    /// Handled by the shell itself, as it needs the command's redirections.
+    Exec,
+    Unknown,
+}
+
+impl FromStr for ShellCommand {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exit" => Ok(ShellCommand::Exit),
+            "echo" => Ok(ShellCommand::Echo),
+            "type" => Ok(ShellCommand::Type),
+            "pwd" => Ok(ShellCommand::Pwd),
+            "cd" => Ok(ShellCommand::Cd),
+            "export" => Ok(ShellCommand::Export),
+            "unset" => Ok(ShellCommand::Unset),
+            "shopt" => Ok(ShellCommand::Shopt),
+            "set" => Ok(ShellCommand::Set),
+            "break" => Ok(ShellCommand::Break),
+            "continue" => Ok(ShellCommand::Continue),
+            "return" => Ok(ShellCommand::Return),
+            "local" => Ok(ShellCommand::Local),
+            "shift" => Ok(ShellCommand::Shift),
+            "source" | "." => Ok(ShellCommand::Source),
+            "eval" => Ok(ShellCommand::Eval),
+            "alias" => Ok(ShellCommand::Alias),
+            "unalias" => Ok(ShellCommand::Unalias),
+            "jobs" => Ok(ShellCommand::Jobs),
+            "fg" => Ok(ShellCommand::Fg),
+            "bg" => Ok(ShellCommand::Bg),
+            "wait" => Ok(ShellCommand::Wait),
+            "disown" => Ok(ShellCommand::Disown),
+            "kill" => Ok(ShellCommand::Kill),
+            "trap" => Ok(ShellCommand::Trap),
+            "exec" => Ok(ShellCommand::Exec),
+            _ => Ok(ShellCommand::Unknown),
+        }
+    }
+}
+
+/// Runs a builtin and returns its exit status.
+pub fn run(shell: &mut Shell, command: ShellCommand, args: Vec<String>) -> i32 {
+    match command {
+        ShellCommand::Echo => handle_echo(args),
+        ShellCommand::Exit => handle_exit(shell, args),
+        ShellCommand::Pwd => handle_pwd(),
+        ShellCommand::Cd => handle_cd(shell, args),
+        ShellCommand::Export => handle_export(shell, args),
+        ShellCommand::Unset => handle_unset(shell, args),
+        ShellCommand::Shopt => handle_shopt(shell, args),
+        ShellCommand::Set => handle_set(shell, args),
+        ShellCommand::Break => handle_loop_control(shell, args, false),
+        ShellCommand::Continue => handle_loop_control(shell, args, true),
+        ShellCommand::Return => handle_return(shell, args),
+        ShellCommand::Local => handle_local(shell, args),
+        ShellCommand::Shift => handle_shift(shell, args),
+        ShellCommand::Source => handle_source(shell, args),
+        ShellCommand::Eval => handle_eval(shell, args),
+        ShellCommand::Alias => handle_alias(shell, args),
+        ShellCommand::Unalias => handle_unalias(shell, args),
+        ShellCommand::Type => handle_type(shell, args),
+        ShellCommand::Jobs => handle_jobs(shell, args),
+        ShellCommand::Fg => handle_fg(shell, args),
+        ShellCommand::Bg => handle_bg(shell, args),
+        ShellCommand::Wait => handle_wait(shell, args),
+        ShellCommand::Disown => handle_disown(shell, args),
+        ShellCommand::Kill => handle_kill(shell, args),
+        ShellCommand::Trap => handle_trap(shell, args),
+        ShellCommand::Exec | ShellCommand::Unknown => 127,
+    }
+}
+
+fn handle_echo(args: Vec<String>) -> i32 {
+    let mut stdout = io::stdout();
+    if let Err(err) = writeln!(stdout, "{}", args.join(" ")).and_then(|_| stdout.flush()) {
+        eprintln!("echo: write error: {}", shell::io_error_message(&err));
+        return 1;
+    }
+    0
+}
+
+fn handle_exit(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let status = match args.as_slice() {
+        [] => shell.last_status,
+        [code] => match code.parse::<i64>() {
+            // Statuses wrap to 0..=255 the way the kernel truncates them
+            Ok(code) => (code & 0xff) as i32,
+            Err(_) => {
+                eprintln!("exit: {}: numeric argument required", code);
+                2
+            }
+        },
+        _ => {
+            eprintln!("exit: too many arguments");
+            return 1;
+        }
+    };
+    shell.exit(status);
+}
+
+fn handle_pwd() -> i32 {
+    match std::env::current_dir() {
+        Ok(path) => {
+            println!("{}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("pwd: {}", e);
+            1
+        }
+    }
+}
+
+fn handle_cd(shell: &Shell, args: Vec<String>) -> i32 {
+    let path = match args.first() {
+        Some(path) => path.to_string(),
+        None => match shell.vars.get("HOME") {
+            Some(home) => home.to_string(),
+            None => {
+                eprintln!("cd: HOME not set");
+                return 1;
+            }
+        },
+    };
+
+    match std::env::set_current_dir(&path) {
+        Ok(_) => 0,
+        Err(_) => {
+            eprintln!("cd: {}: No such file or directory", path);
+            1
+        }
+    }
+}
+
+fn handle_type(shell: &Shell, args: Vec<String>) -> i32 {
+    let mut status = 0;
+    for path in &args {
+        let current_path = path.parse::<ShellCommand>().unwrap();
+        if let Some(value) = shell.aliases.get(path) {
+            println!("{} is aliased to `{}'", path, value);
+        } else if shell.functions.contains_key(path) {
+            println!("{} is a function", path);
+        } else if current_path != ShellCommand::Unknown {
+            println!("{} is a shell builtin", path);
+        } else {
+            let result = shell::handle_exec_function(path);
+            if !result.is_empty() {
+                println!("{} is {}", path, result);
+            } else {
+                println!("{}: not found", path);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+/// `alias [-p] [name[=value]...]`: defines aliases, or prints them as
+/// commands that would recreate them.
+fn handle_alias(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let names: Vec<&String> = args.iter().filter(|arg| *arg != "-p").collect();
+    if names.is_empty() {
+        for (name, value) in &shell.aliases {
+            println!("alias {}='{}'", name, escape_single_quoted(value));
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in names {
+        match arg.split_once('=') {
+            Some((name, _)) if !is_valid_alias_name(name) => {
+                eprintln!("alias: `{}': invalid alias name", name);
+                status = 1;
+            }
+            Some((name, value)) => {
+                shell.aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match shell.aliases.get(arg.as_str()) {
+                Some(value) => println!("alias {}='{}'", arg, escape_single_quoted(value)),
+                None => {
+                    eprintln!("alias: {}: not found", arg);
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+/// `unalias [-a] name...`
+fn handle_unalias(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.iter().any(|arg| arg == "-a") {
+        shell.aliases.clear();
+        return 0;
+    }
+    if args.is_empty() {
+        eprintln!("unalias: usage: unalias [-a] name [name ...]");
+        return 2;
+    }
+    let mut status = 0;
+    for name in &args {
+        if shell.aliases.remove(name).is_none() {
+            eprintln!("unalias: {}: not found", name);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Alias names can't contain anything the lexer would split a word at, nor
+/// quotes or expansions.
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "/$`'\"\\=|&;()<>".contains(c))
+}
+
+fn handle_export(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let names: Vec<&String> = args.iter().filter(|arg| *arg != "-p").collect();
+    if names.is_empty() {
+        for (name, value) in shell.vars.exported() {
+            println!("declare -x {}=\"{}\"", name, escape_double_quoted(value));
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            eprintln!("export: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        shell.vars.export(name, value);
+    }
+    status
+}
+
+fn handle_unset(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let functions = args.iter().any(|arg| arg == "-f");
+    let mut status = 0;
+    for name in args.iter().filter(|arg| *arg != "-v" && *arg != "-f") {
+        if functions {
+            shell.functions.remove(name);
+            continue;
+        }
+        if !is_valid_name(name) {
+            eprintln!("unset: `{}': not a valid identifier", name);
+            status = 1;
+            continue;
+        }
+        shell.vars.unset(name);
+    }
+    status
+}
+
+/// `return [n]`: leaves the current function with status `n`, or with the
+/// status of the last command.
+fn handle_return(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if shell.frames.is_empty() && shell.source_depth == 0 {
+        eprintln!("return: can only `return' from a function or sourced script");
+        return 2;
+    }
+    let status = match args.first() {
+        None => shell.last_status,
+        Some(arg) => match arg.parse::<i64>() {
+            Ok(n) => (n & 0xff) as i32,
+            Err(_) => {
+                eprintln!("return: {}: numeric argument required", arg);
+                2
+            }
+        },
+    };
+    shell.flow = Some(Flow::Return);
+    status
+}
+
+/// `local name[=value]...`: gives variables a value that only lasts until
+/// the current function returns.
+fn handle_local(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let Some(frame) = shell.frames.last_mut() else {
+        eprintln!("local: can only be used in a function");
+        return 1;
+    };
+    if args.is_empty() {
+        for (name, _) in &frame.locals {
+            if let Some(value) = shell.vars.get(name) {
+                println!("{}={}", name, value);
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for arg in &args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            eprintln!("local: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        // Only the first `local` in a call saves the outer value
+        if !frame.locals.iter().any(|(local, _)| local == name) {
+            let old = shell.vars.lookup(name).cloned();
+            frame.locals.push((name.to_string(), old));
+            shell.vars.unset(name);
+        }
+        if let Some(value) = value {
+            shell.vars.set(name, value);
+        }
+    }
+    status
+}
+
+/// `source file [arg...]` or `. file [arg...]`: runs a file's commands in
+/// the current shell. Arguments replace the positional parameters while it
+/// runs. A name without a `/` is looked up in PATH, then the current
+/// directory.
+fn handle_source(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let Some((name, args)) = args.split_first() else {
+        eprintln!("source: filename argument required");
+        return 2;
+    };
+    let path = if name.contains('/') {
+        None
+    } else {
+        let dirs = shell.vars.get("PATH").unwrap_or_default();
+        dirs.split(':')
+            .map(|dir| format!("{}/{}", dir, name))
+            .find(|path| Path::new(path).is_file())
+    };
+    let path = path.unwrap_or_else(|| name.clone());
+
+    let saved = (!args.is_empty()).then(|| std::mem::replace(&mut shell.positional, args.to_vec()));
+    let return_trap = shell.traps.get(Condition::Return).map(str::to_string);
+    shell.source_depth += 1;
+    let result = shell.source_file(&path);
+    shell.source_depth -= 1;
+    if let Ok(status) = result {
+        shell.run_return_trap(return_trap.as_deref(), status);
+    }
+    if let Some(positional) = saved {
+        shell.positional = positional;
+    }
+    if shell.flow == Some(Flow::Return) {
+        shell.flow = None;
+    }
+
+    match result {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("{}: {}", name, shell::io_error_message(&err));
+            1
+        }
+    }
+}
+
+/// `eval [arg...]`: joins the arguments with spaces and runs the result as
+/// shell input.
+fn handle_eval(shell: &mut Shell, args: Vec<String>) -> i32 {
+    shell.run_script(&args.join(" "), "eval")
+}
+
+/// `shift [n]`: drops the first `n` positional parameters.
+fn handle_shift(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let count = match args.first() {
+        None => 1,
+        Some(arg) => match arg.parse::<i64>() {
+            Ok(n) if n >= 0 => n as usize,
+            Ok(_) => {
+                eprintln!("shift: {}: shift count out of range", arg);
+                return 1;
+            }
+            Err(_) => {
+                eprintln!("shift: {}: numeric argument required", arg);
+                return 1;
+            }
+        },
+    };
+    if count > shell.positional.len() {
+        return 1;
+    }
+    shell.positional.drain(..count);
+    0
+}
+
+/// `break [n]` and `continue [n]`: leave or restart the `n`th enclosing loop.
+fn handle_loop_control(shell: &mut Shell, args: Vec<String>, is_continue: bool) -> i32 {
+    let name = if is_continue { "continue" } else { "break" };
+    let count = match args.first() {
+        None => 1,
+        Some(arg) => match arg.parse::<i64>() {
+            Ok(n) if n >= 1 => n as usize,
+            Ok(_) => {
+                eprintln!("{}: {}: loop count out of range", name, arg);
+                return 1;
+            }
+            Err(_) => {
+                eprintln!("{}: {}: numeric argument required", name, arg);
+                return 1;
+            }
+        },
+    };
+    if shell.loop_depth == 0 {
+        eprintln!(
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            name
+        );
+        return 0;
+    }
+
+    // Counting past the outermost loop just leaves all of them
+    let count = count.min(shell.loop_depth);
+    shell.flow = Some(if is_continue {
+        Flow::Continue(count)
+    } else {
+        Flow::Break(count)
+    });
+    0
+}
+
+/// `jobs [-lp] [jobspec...]`: lists jobs with their states, or with `-p`
+/// only their process group ids. Finished jobs are forgotten once listed.
+fn handle_jobs(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let (mut long, mut pids) = (false, false);
+    let mut specs = Vec::new();
+    for arg in &args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() && specs.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'l' => long = true,
+                        'p' => pids = true,
+                        _ => {
+                            eprintln!("jobs: -{}: invalid option", flag);
+                            eprintln!("jobs: usage: jobs [-lp] [jobspec ...]");
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => specs.push(arg.as_str()),
+        }
+    }
+
+    shell.jobs.poll();
+    let mut status = 0;
+    let ids = if specs.is_empty() {
+        shell.jobs.ids()
+    } else {
+        let mut ids = Vec::new();
+        for spec in specs {
+            match shell.jobs.resolve(spec) {
+                Ok(id) => ids.push(id),
+                Err(err) => {
+                    eprintln!("jobs: {}", err);
+                    status = 1;
+                }
+            }
+        }
+        ids
+    };
+    for id in ids {
+        let Some(job) = shell.jobs.get(id) else {
+            continue;
+        };
+        if pids {
+            println!("{}", job.pgid);
+        } else {
+            println!("{}", shell.jobs.format(job, long));
+        }
+        if job.is_done() {
+            shell.jobs.remove(id);
+        } else if let Some(job) = shell.jobs.get_mut(id) {
+            job.notified = true;
+        }
+    }
+    status
+}
+
+/// The job `spec` names for builtin `name`, the current job if there is no
+/// spec. Reports the error if there is no such job.
+fn resolve_job(shell: &Shell, name: &str, spec: Option<&String>) -> Option<usize> {
+    let result = match spec {
+        Some(spec) => shell.jobs.resolve(spec),
+        None => shell
+            .jobs
+            .resolve("%+")
+            .map_err(|_| "current: no such job".to_string()),
+    };
+    result.map_err(|err| eprintln!("{}: {}", name, err)).ok()
+}
+
+/// `fg [jobspec]`: resumes a job in the foreground and waits for it.
+fn handle_fg(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if !shell.job_control {
+        eprintln!("fg: no job control");
+        return 1;
+    }
+    shell.jobs.poll();
+    let Some(id) = resolve_job(shell, "fg", args.first()) else {
+        return 1;
+    };
+    let mut job = shell.jobs.remove(id).unwrap();
+    if job.is_done() {
+        eprintln!("fg: job has terminated");
+        return 1;
+    }
+    println!("{}", job.command);
+    let _ = job.signal(Some(Signal::SIGCONT), true);
+    job.mark_running();
+    shell.wait_foreground(job)
+}
+
+/// `bg [jobspec...]`: resumes stopped jobs in the background.
+fn handle_bg(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if !shell.job_control {
+        eprintln!("bg: no job control");
+        return 1;
+    }
+    shell.jobs.poll();
+    let specs: Vec<Option<&String>> = if args.is_empty() {
+        vec![None]
+    } else {
+        args.iter().map(Some).collect()
+    };
+    let mut status = 0;
+    for spec in specs {
+        let Some(id) = resolve_job(shell, "bg", spec) else {
+            status = 1;
+            continue;
+        };
+        let job = shell.jobs.get(id).unwrap();
+        if job.is_done() {
+            eprintln!("bg: job has terminated");
+            status = 1;
+            continue;
+        }
+        if !job.is_stopped() {
+            eprintln!("bg: job {} already in background", id);
+            continue;
+        }
+        println!("[{}]{} {} &", id, shell.jobs.marker(id), job.command);
+        let _ = job.signal(Some(Signal::SIGCONT), true);
+        if let Some(job) = shell.jobs.get_mut(id) {
+            job.mark_running();
+        }
+    }
+    status
+}
+
+/// `wait [pid|jobspec...]`: waits for the given jobs, or all of them, to
+/// finish. The status is that of the last one named, or 0 without any.
+fn handle_wait(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let ids = if args.is_empty() {
+        shell.jobs.ids()
+    } else {
+        Vec::new()
+    };
+    let mut status = 0;
+    for id in ids {
+        wait_for_job(shell, id);
+    }
+    for arg in &args {
+        let found = if arg.starts_with('%') {
+            shell
+                .jobs
+                .resolve(arg)
+                .map_err(|err| format!("wait: {}", err))
+        } else {
+            match arg.parse::<i32>() {
+                Ok(pid) => shell
+                    .jobs
+                    .find_pid(Pid::from_raw(pid))
+                    .ok_or_else(|| format!("wait: pid {} is not a child of this shell", pid)),
+                Err(_) => {
+                    eprintln!("wait: `{}': not a pid or valid job spec", arg);
+                    status = 2;
+                    continue;
+                }
+            }
+        };
+        status = match found {
+            Ok(id) => wait_for_job(shell, id),
+            Err(err) => {
+                eprintln!("{}", err);
+                127
+            }
+        };
+    }
+    status
+}
+
+/// Waits for a job to finish or stop, forgetting it if it finished.
+fn wait_for_job(shell: &mut Shell, id: usize) -> i32 {
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return 127;
+    };
+    job.wait();
+    let status = job.status();
+    if job.is_done() {
+        shell.jobs.remove(id);
+    }
+    status
+}
+
+/// `disown [-a|-r] [jobspec...]`: removes jobs from the table, all of them
+/// with `-a` or the running ones with `-r`, so the shell forgets them.
+fn handle_disown(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let (mut all, mut running) = (false, false);
+    let mut specs = Vec::new();
+    for arg in &args {
+        match arg.as_str() {
+            "-a" if specs.is_empty() => all = true,
+            "-r" if specs.is_empty() => running = true,
+            arg if arg.starts_with('-') && arg.len() > 1 && specs.is_empty() => {
+                eprintln!("disown: {}: invalid option", arg);
+                eprintln!("disown: usage: disown [-a|-r] [jobspec ...]");
+                return 2;
+            }
+            _ => specs.push(arg),
+        }
+    }
+
+    if specs.is_empty() && (all || running) {
+        for id in shell.jobs.ids() {
+            let stopped = shell.jobs.get(id).is_some_and(|job| job.is_stopped());
+            if all || !stopped {
+                shell.jobs.remove(id);
+            }
+        }
+        return 0;
+    }
+    let specs: Vec<Option<&String>> = if specs.is_empty() {
+        vec![None]
+    } else {
+        specs.into_iter().map(Some).collect()
+    };
+    let mut status = 0;
+    for spec in specs {
+        match resolve_job(shell, "disown", spec) {
+            Some(id) => {
+                shell.jobs.remove(id);
+            }
+            None => status = 1,
+        }
+    }
+    status
+}
+
+/// `kill [-s sigspec | -n signum | -sigspec] pid|jobspec...` sends a signal,
+/// SIGTERM by default, to processes or jobs; `kill -l [signum...]` lists
+/// signal names.
+fn handle_kill(shell: &mut Shell, args: Vec<String>) -> i32 {
+    const USAGE: &str = "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]";
+    let mut signal = Some(Signal::SIGTERM);
+    let mut targets = args.as_slice();
+    match targets.first().map(String::as_str) {
+        Some("-l" | "-L") => return list_signals(&targets[1..]),
+        Some("-s" | "-n") => {
+            let Some(spec) = targets.get(1) else {
+                eprintln!("kill: {}: option requires an argument", targets[0]);
+                eprintln!("{}", USAGE);
+                return 2;
+            };
+            match parse_kill_signal(spec) {
+                Some(parsed) => signal = parsed,
+                None => return 1,
+            }
+            targets = &targets[2..];
+        }
+        Some("--") => targets = &targets[1..],
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+            match parse_kill_signal(&arg[1..]) {
+                Some(parsed) => signal = parsed,
+                None => return 1,
+            }
+            targets = &targets[1..];
+        }
+        _ => {}
+    }
+    if targets.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let mut status = 0;
+    for target in targets {
+        let result = if target.starts_with('%') {
+            let id = match shell.jobs.resolve(target) {
+                Ok(id) => id,
+                Err(err) => {
+                    eprintln!("kill: {}", err);
+                    status = 1;
+                    continue;
+                }
+            };
+            let job = shell.jobs.get(id).unwrap();
+            let result = job.signal(signal, shell.job_control);
+            // A stopped job can't act on a signal to end until it runs again
+            if job.is_stopped() && matches!(signal, Some(Signal::SIGTERM | Signal::SIGHUP)) {
+                let _ = job.signal(Some(Signal::SIGCONT), shell.job_control);
+            }
+            result
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => signal::kill(Pid::from_raw(pid), signal),
+                Err(_) => {
+                    eprintln!("kill: {}: arguments must be process or job IDs", target);
+                    status = 1;
+                    continue;
+                }
+            }
+        };
+        if let Err(err) = result {
+            eprintln!("kill: ({}) - {}", target, err.desc());
+            status = 1;
+        }
+    }
+    status
+}
+
+/// A signal for `kill`, where 0 (`None`) only checks that the process
+/// exists. Reports an invalid one.
+fn parse_kill_signal(spec: &str) -> Option<Option<Signal>> {
+    if spec == "0" {
+        return Some(None);
+    }
+    let signal = jobs::parse_signal(spec);
+    if signal.is_none() {
+        eprintln!("kill: {}: invalid signal specification", spec);
+    }
+    signal.map(Some)
+}
+
+/// `kill -l`: lists all signals by number and name, or converts each
+/// argument between number and name. An exit status above 128 names the
+/// signal that caused it.
+fn list_signals(args: &[String]) -> i32 {
+    if args.is_empty() {
+        print_signal_table();
+        return 0;
+    }
+    let mut status = 0;
+    for arg in args {
+        let signal = match arg.parse::<i32>() {
+            Ok(number) => Signal::try_from(if number > 128 { number - 128 } else { number })
+                .ok()
+                .map(|signal| jobs::signal_name(signal).to_string()),
+            Err(_) => jobs::parse_signal(arg).map(|signal| (signal as i32).to_string()),
+        };
+        match signal {
+            Some(text) => println!("{}", text),
+            None => {
+                eprintln!("kill: {}: invalid signal specification", arg);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+/// `set [-+efnuxC] [-+o [option]] [--] [arg...]`: turns options on with
+/// `-` or off with `+`, and sets the positional parameters to any
+/// arguments left. `-o` alone lists the options, `+o` alone prints commands
+/// that would restore them, and no arguments at all lists the variables.
+fn handle_set(shell: &mut Shell, args: Vec<String>) -> i32 {
+    if args.is_empty() {
+        for (name, value) in shell.vars.all() {
+            println!("{}={}", name, shell::quote_word(value));
+        }
+        return 0;
+    }
+
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        if arg == "--" {
+            shell.positional = args[i + 1..].to_vec();
+            return 0;
+        }
+        // `set - arg...` turns off tracing and takes the rest as arguments
+        if arg == "-" {
+            shell.options.xtrace = false;
+            i += 1;
+            break;
+        }
+        let (on, flags) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(flags), _) if !flags.is_empty() => (true, flags),
+            (_, Some(flags)) if !flags.is_empty() => (false, flags),
+            _ => break,
+        };
+        for flag in flags.chars() {
+            if flag == 'o' {
+                let Some(name) = args.get(i + 1) else {
+                    print_set_options(shell, !on);
+                    continue;
+                };
+                i += 1;
+                if !shell.options.set(name, on) {
+                    eprintln!("set: {}: invalid option name", name);
+                    return 1;
+                }
+                continue;
+            }
+            match SetOptions::flag_name(flag) {
+                Some(name) => {
+                    shell.options.set(name, on);
+                }
+                None => {
+                    eprintln!(
+                        "set: {}{}: invalid option",
+                        if on { '-' } else { '+' },
+                        flag
+                    );
+                    eprintln!("set: usage: set [-efnuxC] [-o option-name] [--] [arg ...]");
+                    return 2;
+                }
+            }
+        }
+        i += 1;
+    }
+    if i < args.len() {
+        shell.positional = args[i..].to_vec();
+    }
+    0
+}
+
+/// Lists the `set -o` options as `name on|off`, or with `commands` as the
+/// `set` commands that would restore them.
+fn print_set_options(shell: &Shell, commands: bool) {
+    for name in SetOptions::NAMES {
+        let on = shell.options.get(name) == Some(true);
+        if commands {
+            println!("set {}o {}", if on { '-' } else { '+' }, name);
+        } else {
+            println!("{:<15}\t{}", name, if on { "on" } else { "off" });
+        }
+    }
+}
+
+/// Lists signals by number and name, five to a line.
+fn print_signal_table() {
+    let names: Vec<String> = Signal::iterator()
+        .map(|signal| format!("{:2}) {}", signal as i32, signal.as_str()))
+        .collect();
+    for row in names.chunks(5) {
+        println!("{}", row.join("\t"));
+    }
+}
+
+/// `trap [-lp] [[action] condition...]`: sets the command to run on
+/// signals or on `EXIT`, `DEBUG`, `ERR` and `RETURN`. An empty action
+/// ignores the signal, and `-` resets it. Without an action, lists the
+/// traps as commands that would recreate them.
+fn handle_trap(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let mut args = args.as_slice();
+    let mut print = false;
+    match args.first().map(String::as_str) {
+        Some("-l") => {
+            print_signal_table();
+            return 0;
+        }
+        Some("-p") => {
+            print = true;
+            args = &args[1..];
+        }
+        Some("--") => args = &args[1..],
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+            eprintln!("trap: {}: invalid option", arg);
+            eprintln!("trap: usage: trap [-lp] [[action] signal_spec ...]");
+            return 2;
+        }
+        _ => {}
+    }
+
+    if print || args.is_empty() {
+        let mut status = 0;
+        let traps: Vec<(Condition, String)> = if args.is_empty() {
+            shell
+                .traps
+                .iter()
+                .map(|(condition, action)| (condition, action.to_string()))
+                .collect()
+        } else {
+            let mut traps = Vec::new();
+            for spec in args {
+                match parse_trap_condition(spec) {
+                    Some(condition) => {
+                        if let Some(action) = shell.traps.get(condition) {
+                            traps.push((condition, action.to_string()));
+                        }
+                    }
+                    None => status = 1,
+                }
+            }
+            traps
+        };
+        for (condition, action) in traps {
+            println!(
+                "trap -- '{}' {}",
+                escape_single_quoted(&action),
+                condition.name()
+            );
+        }
+        return status;
+    }
+
+    // A lone condition, or a number first, means resetting them all
+    let (action, specs) = if args.len() == 1 || args[0].parse::<u32>().is_ok() {
+        (None, args)
+    } else if args[0] == "-" {
+        (None, &args[1..])
+    } else {
+        (Some(&args[0]), &args[1..])
+    };
+    let mut status = 0;
+    for spec in specs {
+        let Some(condition) = parse_trap_condition(spec) else {
+            status = 1;
+            continue;
+        };
+        match action {
+            Some(action) => shell.traps.set(condition, action.clone()),
+            None => shell.traps.reset(condition, shell.job_control),
+        }
+    }
+    status
+}
+
+/// A trap condition, reporting an invalid one.
+fn parse_trap_condition(spec: &str) -> Option<Condition> {
+    let condition = Condition::parse(spec);
+    if condition.is_none() {
+        eprintln!("trap: {}: invalid signal specification", spec);
+    }
+    condition
+}
+
+/// `shopt [-s|-u] [-pq] [name...]`: sets, unsets or reports shell options.
+/// Reporting returns 1 if any named option is off.
+fn handle_shopt(shell: &mut Shell, args: Vec<String>) -> i32 {
+    let (mut set, mut unset, mut print, mut quiet) = (false, false, false, false);
+    let mut names = Vec::new();
+    for arg in &args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() && names.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        's' => set = true,
+                        'u' => unset = true,
+                        'p' => print = true,
+                        'q' => quiet = true,
+                        _ => {
+                            eprintln!("shopt: -{}: invalid option", flag);
+                            return 2;
+                        }
+                    }
+                }
+            }
+            _ => names.push(arg.as_str()),
+        }
+    }
+    if set && unset {
+        eprintln!("shopt: cannot set and unset shell options simultaneously");
+        return 1;
+    }
+
+    let mut status = 0;
+    for name in &names {
+        if shell.shopts.get(name).is_none() {
+            eprintln!("shopt: {}: invalid shell option name", name);
+            status = 1;
+        }
+    }
+
+    if names.is_empty() {
+        names = Shopts::NAMES.to_vec();
+        // With -s or -u and no names, only list options in that state
+        if set || unset {
+            names.retain(|name| shell.shopts.get(name) == Some(set));
+        }
+    } else if set || unset {
+        for name in &names {
+            shell.shopts.set(name, set);
+        }
+        return status;
+    }
+
+    for name in names {
+        let Some(on) = shell.shopts.get(name) else {
+            continue;
+        };
+        if !on {
+            status = 1;
+        }
+        if quiet {
+            continue;
+        }
+        if print {
+            println!("shopt {} {}", if on { "-s" } else { "-u" }, name);
+        } else {
+            println!("{:<15}\t{}", name, if on { "on" } else { "off" });
+        }
+    }
+    status
+}
+
+/// Escapes a value for printing inside single quotes, closing and
+/// reopening the quotes around each `'`.
+fn escape_single_quoted(value: &str) -> String {
+    value.replace('\'', "'\\''")
+}
+
+/// Escapes a value for display inside double quotes.
+fn escape_double_quoted(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
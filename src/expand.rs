@@ -0,0 +1,644 @@
+use nix::unistd::User;
+use thiserror::Error;
+
+use crate::arith;
+use crate::ast::{ParamExpansion, ParamOp, ReplaceMode, Word, WordPart};
+use crate::brace;
+use crate::glob;
+use crate::lexer::{Lexer, TokenKind};
+use crate::pattern::{self, Pattern};
+use crate::shell::Shell;
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/// An expansion that aborts the command, such as `${v:?message}`.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct ExpandError(pub String);
+
+/// A run of expanded text, remembering where it came from.
+#[derive(Debug, Clone)]
+struct Piece {
+    text: String,
+    /// Came from a quoted context, so it is never split or treated as a pattern.
+    quoted: bool,
+    /// The unquoted result of an expansion, subject to field splitting.
+    splittable: bool,
+    /// Not text but a boundary between positional parameters in `$@`, which
+    /// always separates fields when quoted.
+    breaks_field: bool,
+}
+
+impl Piece {
+    fn literal(text: impl Into<String>, quoted: bool) -> Self {
+        Self {
+            text: text.into(),
+            quoted,
+            splittable: false,
+            breaks_field: false,
+        }
+    }
+
+    /// The result of an expansion, which is split unless quoted.
+    fn expansion(text: String, quoted: bool) -> Self {
+        Self {
+            text,
+            quoted,
+            splittable: !quoted,
+            breaks_field: false,
+        }
+    }
+
+    /// Joins as a space where no field splitting happens.
+    fn field_break(quoted: bool) -> Self {
+        Self {
+            text: " ".to_string(),
+            quoted,
+            splittable: false,
+            breaks_field: true,
+        }
+    }
+}
+
+/// One field produced by field splitting.
+#[derive(Debug, Default)]
+struct Field {
+    pieces: Vec<Piece>,
+}
+
+impl Field {
+    fn push_str(&mut self, text: &str, quoted: bool) {
+        match self.pieces.last_mut() {
+            Some(last) if last.quoted == quoted => last.text.push_str(text),
+            _ => self.pieces.push(Piece::literal(text, quoted)),
+        }
+    }
+
+    fn into_string(self) -> String {
+        self.pieces.into_iter().map(|piece| piece.text).collect()
+    }
+
+    /// The field as a pattern, with quoted pieces escaped.
+    fn to_pattern(&self) -> String {
+        self.pieces
+            .iter()
+            .map(|piece| {
+                if piece.quoted {
+                    pattern::escape(&piece.text)
+                } else {
+                    piece.text.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Shell {
+    /// Expands command words into fields: brace, tilde and parameter
+    /// expansion, field splitting, pathname expansion and quote removal.
+    pub fn expand_words(&mut self, words: &[Word]) -> Result<Vec<String>, ExpandError> {
+        let mut fields = Vec::new();
+        for word in words.iter().flat_map(brace::expand) {
+            let pieces = self.expand_pieces(&word)?;
+            for field in self.split_fields(pieces) {
+                self.glob_field(field, &mut fields)?;
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Replaces a field containing unquoted pattern characters with the
+    /// pathnames it matches. A pattern that matches nothing is kept as is,
+    /// unless `nullglob` or `failglob` is set.
+    fn glob_field(&mut self, field: Field, fields: &mut Vec<String>) -> Result<(), ExpandError> {
+        let pattern = field.to_pattern();
+        if self.options.noglob || !pattern::has_pattern_chars(&pattern) {
+            fields.push(field.into_string());
+            return Ok(());
+        }
+
+        let matches = glob::glob(&pattern, &self.shopts);
+        if !matches.is_empty() {
+            fields.extend(matches);
+        } else if self.shopts.failglob {
+            return Err(self.fatal_error(1, format!("no match: {}", field.into_string())));
+        } else if !self.shopts.nullglob {
+            fields.push(field.into_string());
+        }
+        Ok(())
+    }
+
+    /// Expands a word to exactly one string, without field splitting, as
+    /// for assignment values and redirection targets.
+    pub fn expand_word(&mut self, word: &Word) -> Result<String, ExpandError> {
+        Ok(self
+            .expand_pieces(word)?
+            .into_iter()
+            .map(|piece| piece.text)
+            .collect())
+    }
+
+    /// Expands text as if it were inside double quotes, as for `$ENV`.
+    pub fn expand_string(&mut self, text: &str) -> Result<String, ExpandError> {
+        let quoted = format!("\"{}\"", text.replace('"', "\\\""));
+        let token = Lexer::new(&quoted, &self.aliases)
+            .next_token()
+            .map_err(|err| ExpandError(err.message))?;
+        match token.kind {
+            TokenKind::Word(word) => self.expand_word(&word),
+            _ => Ok(text.to_string()),
+        }
+    }
+
+    /// Expands a word into a pattern, escaping the parts that were quoted so
+    /// they only match themselves.
+    pub fn expand_pattern(&mut self, word: &Word) -> Result<String, ExpandError> {
+        Ok(self
+            .expand_pieces(word)?
+            .into_iter()
+            .map(|piece| {
+                if piece.quoted {
+                    pattern::escape(&piece.text)
+                } else {
+                    piece.text
+                }
+            })
+            .collect())
+    }
+
+    fn expand_pieces(&mut self, word: &Word) -> Result<Vec<Piece>, ExpandError> {
+        let mut pieces = Vec::new();
+        let mut parts = word.parts.as_slice();
+
+        if let Some((WordPart::Literal(text), rest)) = parts.split_first() {
+            if let Some((home, remainder)) = self.expand_tilde(text) {
+                pieces.push(Piece::literal(home, true));
+                if !remainder.is_empty() {
+                    pieces.push(Piece::literal(remainder, false));
+                }
+                parts = rest;
+            }
+        }
+
+        self.expand_parts(parts, false, &mut pieces)?;
+        Ok(pieces)
+    }
+
+    fn expand_parts(
+        &mut self,
+        parts: &[WordPart],
+        quoted: bool,
+        pieces: &mut Vec<Piece>,
+    ) -> Result<(), ExpandError> {
+        for part in parts {
+            match part {
+                WordPart::Literal(text) => pieces.push(Piece::literal(text.clone(), quoted)),
+                WordPart::Quoted(text) => pieces.push(Piece::literal(text.clone(), true)),
+                WordPart::DoubleQuoted(inner) => {
+                    // Keeps `""` as an (empty) field of its own, except that
+                    // `"$@"` with no parameters is no field at all
+                    let all_params = matches!(
+                        inner.as_slice(),
+                        [WordPart::Param(ParamExpansion { name, op: ParamOp::Plain })] if name == "@"
+                    );
+                    if !(all_params && self.positional.is_empty()) {
+                        pieces.push(Piece::literal("", true));
+                    }
+                    self.expand_parts(inner, true, pieces)?;
+                }
+                WordPart::Param(param) => self.expand_param(param, quoted, pieces)?,
+                WordPart::CommandSub(list) => {
+                    let text = self.capture_output(list);
+                    pieces.push(Piece::expansion(text, quoted));
+                }
+                WordPart::Arithmetic(expr) => {
+                    let text = self.eval_arithmetic(expr)?.to_string();
+                    pieces.push(Piece::expansion(text, quoted));
+                }
+                WordPart::ProcessSub { list, output } => {
+                    let path = self.process_substitution(list, *output)?;
+                    pieces.push(Piece::expansion(path, quoted));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_param(
+        &mut self,
+        param: &ParamExpansion,
+        quoted: bool,
+        pieces: &mut Vec<Piece>,
+    ) -> Result<(), ExpandError> {
+        let name = &param.name;
+        if name == "@" || name == "*" {
+            let params = match &param.op {
+                ParamOp::Plain => Some(self.positional.clone()),
+                ParamOp::Substring { offset, length } => {
+                    Some(self.positional_slice(offset, length.as_ref())?)
+                }
+                _ => None,
+            };
+            if let Some(params) = params {
+                self.push_params(params, name == "*", quoted, pieces);
+                return Ok(());
+            }
+        }
+        let value = self.param(name);
+        let checks_unset = matches!(
+            param.op,
+            ParamOp::Default { .. }
+                | ParamOp::Alternative { .. }
+                | ParamOp::Assign { .. }
+                | ParamOp::Error { .. }
+        );
+        if value.is_none() && self.options.nounset && !checks_unset {
+            return Err(self.fatal_error(127, format!("{}: unbound variable", name)));
+        }
+        // Whether the `:` form (or no form) would treat the value as missing
+        let missing = |colon: bool| match &value {
+            None => true,
+            Some(v) => colon && v.is_empty(),
+        };
+
+        let result = match &param.op {
+            ParamOp::Plain => value,
+            ParamOp::Length if name == "@" || name == "*" => {
+                Some(self.positional.len().to_string())
+            }
+            ParamOp::Length => Some(value.unwrap_or_default().chars().count().to_string()),
+            ParamOp::Default { colon, word } => {
+                if missing(*colon) {
+                    return self.expand_operand(word, quoted, pieces);
+                }
+                value
+            }
+            ParamOp::Alternative { colon, word } => {
+                if !missing(*colon) {
+                    return self.expand_operand(word, quoted, pieces);
+                }
+                None
+            }
+            ParamOp::Assign { colon, word } => {
+                if missing(*colon) {
+                    if !crate::vars::is_valid_name(name) {
+                        return Err(ExpandError(format!("${}: cannot assign in this way", name)));
+                    }
+                    let value = self.expand_word(word)?;
+                    self.vars.set(name, value.clone());
+                    Some(value)
+                } else {
+                    value
+                }
+            }
+            ParamOp::Error { colon, word } => {
+                if missing(*colon) {
+                    let message = self.expand_word(word)?;
+                    let message = if message.is_empty() {
+                        "parameter null or not set".to_string()
+                    } else {
+                        message
+                    };
+                    return Err(self.fatal_error(127, format!("{}: {}", name, message)));
+                }
+                value
+            }
+            ParamOp::RemovePrefix { longest, pattern } => {
+                let value = value.unwrap_or_default();
+                let pattern = Pattern::new(&self.expand_pattern(pattern)?);
+                let end = pattern.match_prefix(&value, *longest).unwrap_or(0);
+                Some(value[end..].to_string())
+            }
+            ParamOp::RemoveSuffix { longest, pattern } => {
+                let value = value.unwrap_or_default();
+                let pattern = Pattern::new(&self.expand_pattern(pattern)?);
+                let start = pattern
+                    .match_suffix(&value, *longest)
+                    .unwrap_or(value.len());
+                Some(value[..start].to_string())
+            }
+            ParamOp::Replace {
+                mode,
+                pattern,
+                replacement,
+            } => {
+                let value = value.unwrap_or_default();
+                let pattern = Pattern::new(&self.expand_pattern(pattern)?);
+                let replacement = self.expand_word(replacement)?;
+                Some(replace(&value, &pattern, &replacement, *mode))
+            }
+            ParamOp::Substring { offset, length } => {
+                let value = value.unwrap_or_default();
+                let offset = self.eval_arithmetic(offset)?;
+                let length = match length {
+                    Some(length) => Some(self.eval_arithmetic(length)?),
+                    None => None,
+                };
+                Some(substring(&value, offset, length).map_err(|err| self.fatal_error(1, err.0))?)
+            }
+            ParamOp::Case {
+                upper,
+                all,
+                pattern,
+            } => {
+                let value = value.unwrap_or_default();
+                let pattern = match pattern {
+                    Some(pattern) => Some(Pattern::new(&self.expand_pattern(pattern)?)),
+                    None => None,
+                };
+                Some(change_case(&value, *upper, *all, pattern.as_ref()))
+            }
+        };
+
+        if let Some(text) = result {
+            pieces.push(Piece::expansion(text, quoted));
+        }
+        Ok(())
+    }
+
+    /// An expansion error that ends a non-interactive shell with `status`,
+    /// as when `${v:?message}` finds `v` unset, reported with the script's
+    /// line. An interactive shell only abandons the command.
+    fn fatal_error(&mut self, status: i32, message: String) -> ExpandError {
+        // A script can't sensibly go on without the value it asked for
+        if !self.interactive {
+            eprintln!("{}: line {}: {}", self.origin, self.line_number, message);
+            self.exit(status);
+        }
+        ExpandError(message)
+    }
+
+    /// Pushes positional parameters as separate fields for `$@` (and
+    /// unquoted `$*`), or joined by the first character of IFS for `"$*"`.
+    fn push_params(&self, params: Vec<String>, star: bool, quoted: bool, pieces: &mut Vec<Piece>) {
+        if star && quoted {
+            let ifs = self.vars.get("IFS").unwrap_or(DEFAULT_IFS);
+            let separator: String = ifs.chars().take(1).collect();
+            pieces.push(Piece::expansion(params.join(&separator), true));
+            return;
+        }
+        for (i, param) in params.into_iter().enumerate() {
+            if i > 0 {
+                pieces.push(Piece::field_break(quoted));
+            }
+            pieces.push(Piece::expansion(param, quoted));
+        }
+    }
+
+    /// The parameters selected by `${@:offset:length}`, where offset 0 is `$0`.
+    fn positional_slice(
+        &mut self,
+        offset: &Word,
+        length: Option<&Word>,
+    ) -> Result<Vec<String>, ExpandError> {
+        let all: Vec<String> = std::iter::once(self.name.clone())
+            .chain(self.positional.iter().cloned())
+            .collect();
+        let offset = self.eval_arithmetic(offset)?;
+        let start = if offset < 0 {
+            all.len() as i64 + offset
+        } else {
+            offset
+        };
+        if start < 0 || start as usize > all.len() {
+            return Ok(Vec::new());
+        }
+        let rest = &all[start as usize..];
+        let count = match length {
+            Some(length) => {
+                let length = self.eval_arithmetic(length)?;
+                if length < 0 {
+                    return Err(
+                        self.fatal_error(1, format!("{}: substring expression < 0", length))
+                    );
+                }
+                (length as usize).min(rest.len())
+            }
+            None => rest.len(),
+        };
+        Ok(rest[..count].to_vec())
+    }
+
+    /// Expands the word operand of `${v:-word}` or `${v:+word}` in place of
+    /// the parameter, so unquoted text in it is split like any expansion.
+    fn expand_operand(
+        &mut self,
+        word: &Word,
+        quoted: bool,
+        pieces: &mut Vec<Piece>,
+    ) -> Result<(), ExpandError> {
+        let start = pieces.len();
+        self.expand_parts(&word.parts, quoted, pieces)?;
+        for piece in &mut pieces[start..] {
+            piece.splittable = !piece.quoted;
+        }
+        Ok(())
+    }
+
+    /// Expands an arithmetic expression, as in `$((expr))` or the operands
+    /// of `${v:offset:length}`, and evaluates it. Unlike a failed `((expr))`
+    /// command, an error here ends a non-interactive shell.
+    fn eval_arithmetic(&mut self, expr: &Word) -> Result<i64, ExpandError> {
+        let text = self.expand_arithmetic(expr)?;
+        arith::eval(&text, &mut self.vars).map_err(|err| self.fatal_error(1, err.0))
+    }
+
+    /// Runs a `(( ... ))` command, which `set -x` traces once expanded.
+    pub fn arithmetic_command(&mut self, expr: &Word) -> Result<i64, ExpandError> {
+        let text = self.expand_arithmetic(expr)?;
+        if self.options.xtrace {
+            self.trace_line(&format!("(( {} ))", text));
+        }
+        arith::eval(&text, &mut self.vars)
+    }
+
+    fn expand_arithmetic(&mut self, expr: &Word) -> Result<String, ExpandError> {
+        let mut pieces = Vec::new();
+        self.expand_parts(&expr.parts, true, &mut pieces)?;
+        Ok(pieces.into_iter().map(|piece| piece.text).collect())
+    }
+
+    /// The value of a variable or special parameter, if it is set.
+    pub fn param(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "-" => {
+                let mut flags = self.options.flags();
+                if self.interactive {
+                    flags.push('i');
+                }
+                Some(flags)
+            }
+            "0" => Some(self.name.clone()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")).filter(|_| !self.positional.is_empty()),
+            _ if name.bytes().all(|b| b.is_ascii_digit()) => {
+                let index: usize = name.parse().ok()?;
+                self.positional.get(index.checked_sub(1)?).cloned()
+            }
+            _ => self.vars.get(name).map(str::to_string),
+        }
+    }
+
+    /// Expands a leading `~` or `~user` in the first literal of a word,
+    /// returning the directory and the text after the tilde prefix.
+    fn expand_tilde<'a>(&self, text: &'a str) -> Option<(String, &'a str)> {
+        let rest = text.strip_prefix('~')?;
+        let (user, remainder) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        let home = if user.is_empty() {
+            self.vars.get("HOME")?.to_string()
+        } else {
+            let user = User::from_name(user).ok()??;
+            user.dir.display().to_string()
+        };
+        Some((home, remainder))
+    }
+
+    /// Splits the unquoted expansion results in `pieces` on `$IFS`.
+    fn split_fields(&self, pieces: Vec<Piece>) -> Vec<Field> {
+        let ifs = self.vars.get("IFS").unwrap_or(DEFAULT_IFS);
+        let mut fields = Vec::new();
+        let mut current = Field::default();
+        // Whether `current` holds anything, even an empty quoted string
+        let mut started = false;
+        // Whether the last delimiter was whitespace that already ended a field
+        let mut ws_ended = false;
+
+        for piece in pieces {
+            if piece.breaks_field {
+                if started || piece.quoted {
+                    fields.push(std::mem::take(&mut current));
+                }
+                started = piece.quoted;
+                ws_ended = false;
+                continue;
+            }
+            if !piece.splittable || ifs.is_empty() {
+                started |= piece.quoted || !piece.text.is_empty();
+                if !piece.text.is_empty() {
+                    ws_ended = false;
+                }
+                current.push_str(&piece.text, piece.quoted);
+                continue;
+            }
+
+            for c in piece.text.chars() {
+                if !ifs.contains(c) {
+                    let mut buf = [0; 4];
+                    current.push_str(c.encode_utf8(&mut buf), false);
+                    started = true;
+                    ws_ended = false;
+                } else if c.is_whitespace() {
+                    if started {
+                        fields.push(std::mem::take(&mut current));
+                        started = false;
+                        ws_ended = true;
+                    }
+                } else {
+                    // A non-whitespace delimiter ends a field even when empty,
+                    // unless whitespace right before it already did
+                    if started || !ws_ended {
+                        fields.push(std::mem::take(&mut current));
+                    }
+                    started = false;
+                    ws_ended = false;
+                }
+            }
+        }
+
+        if started {
+            fields.push(current);
+        }
+        fields
+    }
+}
+
+/// Replaces matches of `pattern` in `value`. Like bash, each match is the
+/// longest one starting at its position.
+fn replace(value: &str, pattern: &Pattern, replacement: &str, mode: ReplaceMode) -> String {
+    match mode {
+        ReplaceMode::Prefix => match pattern.match_prefix(value, true) {
+            Some(end) => format!("{}{}", replacement, &value[end..]),
+            None => value.to_string(),
+        },
+        ReplaceMode::Suffix => match pattern.match_suffix(value, true) {
+            Some(start) => format!("{}{}", &value[..start], replacement),
+            None => value.to_string(),
+        },
+        ReplaceMode::First | ReplaceMode::All => {
+            let mut out = String::new();
+            let mut pos = 0;
+            while pos <= value.len() {
+                let found = pattern
+                    .match_prefix(&value[pos..], true)
+                    .filter(|&len| len > 0);
+                match found {
+                    Some(len) => {
+                        out.push_str(replacement);
+                        pos += len;
+                        if mode == ReplaceMode::First {
+                            break;
+                        }
+                    }
+                    None => match value[pos..].chars().next() {
+                        Some(c) => {
+                            out.push(c);
+                            pos += c.len_utf8();
+                        }
+                        None => break,
+                    },
+                }
+            }
+            if pos < value.len() {
+                out.push_str(&value[pos..]);
+            }
+            out
+        }
+    }
+}
+
+/// `${v:offset:length}` on characters; negative values count from the end.
+/// A start past the end gives nothing, but a negative length that ends
+/// before the start is an error.
+fn substring(value: &str, offset: i64, length: Option<i64>) -> Result<String, ExpandError> {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as i64;
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return Ok(String::new());
+    }
+    let end = match length {
+        None => len,
+        Some(length) if length < 0 => {
+            let end = len + length;
+            if end < start {
+                return Err(ExpandError(format!("{}: substring expression < 0", length)));
+            }
+            end
+        }
+        Some(length) => (start + length).min(len),
+    };
+    Ok(chars[start as usize..end as usize].iter().collect())
+}
+
+/// `${v^}`, `${v^^}`, `${v,}` and `${v,,}`.
+fn change_case(value: &str, upper: bool, all: bool, pattern: Option<&Pattern>) -> String {
+    let mut out = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        let selected = (all || i == 0)
+            && pattern.map_or(true, |pattern| pattern.matches(c.encode_utf8(&mut [0; 4])));
+        if !selected {
+            out.push(c);
+        } else if upper {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+    }
+    out
+}
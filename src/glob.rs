@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+
+use crate::options::Shopts;
+use crate::pattern::Pattern;
+
+/// Expands a pathname pattern (quoted characters already backslash-escaped)
+/// into the sorted list of existing paths it matches.
+pub fn glob(pattern: &str, opts: &Shopts) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let is_last = i == components.len() - 1;
+        if component.is_empty() {
+            // A doubled or trailing slash only keeps directories
+            paths.retain(|path| path.is_empty() || Path::new(path).is_dir());
+            paths.iter_mut().for_each(|path| {
+                if !path.is_empty() && !path.ends_with('/') {
+                    path.push('/');
+                }
+            });
+            continue;
+        }
+
+        let mut next = Vec::new();
+        for base in &paths {
+            if opts.globstar && *component == "**" {
+                expand_globstar(base, is_last, opts, &mut next);
+                continue;
+            }
+            let pattern = Pattern::new(component);
+            match pattern.literal_text() {
+                Some(name) => {
+                    let path = join(base, &name);
+                    if fs::symlink_metadata(&path).is_ok() {
+                        next.push(path);
+                    }
+                }
+                None => {
+                    for name in read_dir_names(base) {
+                        if matches_entry(&pattern, &name, opts) {
+                            next.push(join(base, &name));
+                        }
+                    }
+                }
+            }
+        }
+        if !is_last {
+            next.retain(|path| path.is_empty() || Path::new(path).is_dir());
+        }
+        paths = next;
+    }
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// `**`: the base directory itself and every directory below it, plus all
+/// files when it is the last component.
+fn expand_globstar(base: &str, is_last: bool, opts: &Shopts, out: &mut Vec<String>) {
+    if !is_last || !base.is_empty() {
+        out.push(base.to_string());
+    }
+    for name in read_dir_names(base) {
+        if name.starts_with('.') && !opts.dotglob {
+            continue;
+        }
+        let path = join(base, &name);
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir());
+        if is_dir {
+            expand_globstar(&path, is_last, opts, out);
+        } else if is_last {
+            out.push(path);
+        }
+    }
+}
+
+fn matches_entry(pattern: &Pattern, name: &str, opts: &Shopts) -> bool {
+    if name == "." || name == ".." {
+        return false;
+    }
+    if name.starts_with('.') && !opts.dotglob && !pattern.starts_with_dot() {
+        return false;
+    }
+    pattern.matches_with(name, opts.nocaseglob)
+}
+
+fn read_dir_names(base: &str) -> Vec<String> {
+    let dir = if base.is_empty() { "." } else { base };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn join(base: &str, name: &str) -> String {
+    if base.is_empty() || base.ends_with('/') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory tree, removed again on drop.
+    struct Tree {
+        root: String,
+    }
+
+    impl Tree {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("glob-test-{}-{}", std::process::id(), name))
+                .to_string_lossy()
+                .into_owned();
+            for file in files {
+                let path = Path::new(&root).join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+            Self { root }
+        }
+
+        /// Globs `pattern` under the root, giving paths relative to it.
+        fn glob(&self, pattern: &str, opts: &Shopts) -> Vec<String> {
+            let prefix = format!("{}/", self.root);
+            glob(&format!("{}{}", prefix, pattern), opts)
+                .into_iter()
+                .map(|path| path.strip_prefix(&prefix).unwrap().to_string())
+                .collect()
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn matches_sorted_paths() {
+        let tree = Tree::new(
+            "sorted",
+            &[
+                "b.txt",
+                "a.txt",
+                "c.rs",
+                ".hidden.txt",
+                "sub/d.txt",
+                "sub/deep/e.txt",
+            ],
+        );
+        let opts = Shopts::default();
+        assert_eq!(tree.glob("*.txt", &opts), ["a.txt", "b.txt"]);
+        assert_eq!(tree.glob("?.rs", &opts), ["c.rs"]);
+        assert_eq!(tree.glob("*/*.txt", &opts), ["sub/d.txt"]);
+        assert_eq!(tree.glob("s*/", &opts), ["sub/"]);
+        assert_eq!(tree.glob("*.none", &opts), Vec::<String>::new());
+    }
+
+    #[test]
+    fn hidden_files_need_a_dot_or_dotglob() {
+        let tree = Tree::new("hidden", &["a.txt", ".hidden.txt"]);
+        let mut opts = Shopts::default();
+        assert_eq!(tree.glob("*.txt", &opts), ["a.txt"]);
+        assert_eq!(tree.glob(".*.txt", &opts), [".hidden.txt"]);
+        opts.dotglob = true;
+        assert_eq!(tree.glob("*.txt", &opts), [".hidden.txt", "a.txt"]);
+    }
+
+    #[test]
+    fn nocaseglob() {
+        let tree = Tree::new("nocase", &["README", "readme.md"]);
+        let mut opts = Shopts::default();
+        assert_eq!(tree.glob("read*", &opts), ["readme.md"]);
+        opts.nocaseglob = true;
+        assert_eq!(tree.glob("read*", &opts), ["README", "readme.md"]);
+    }
+
+    #[test]
+    fn globstar() {
+        let tree = Tree::new("globstar", &["a.txt", "sub/b.txt", "sub/deep/c.txt"]);
+        let mut opts = Shopts::default();
+        assert_eq!(tree.glob("**/*.txt", &opts), ["sub/b.txt"]);
+        opts.globstar = true;
+        assert_eq!(
+            tree.glob("**/*.txt", &opts),
+            ["a.txt", "sub/b.txt", "sub/deep/c.txt"]
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! The job table, and the process-group and terminal handling behind job
+//! control.
+
+use std::ffi::CStr;
+use std::io;
+use std::os::fd::AsFd;
+
+use nix::sys::signal::{self, SigHandler, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{self, Pid};
+
+/// Signals an interactive shell ignores so that it is neither stopped nor
+/// killed from the keyboard, and which the commands it starts get back.
+const INTERACTIVE_SIGNALS: [Signal; 5] = [
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGTTIN,
+    Signal::SIGTTOU,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    Stopped(Signal),
+    Exited(i32),
+    /// Killed by a signal, and whether it dumped core.
+    Signaled(Signal, bool),
+}
+
+impl ProcessState {
+    /// The exit status the shell reports, with 128 added to a signal number.
+    pub fn status(self) -> i32 {
+        match self {
+            ProcessState::Running => 0,
+            ProcessState::Exited(code) => code,
+            ProcessState::Stopped(signal) | ProcessState::Signaled(signal, _) => {
+                128 + signal as i32
+            }
+        }
+    }
+
+    fn from_wait(status: WaitStatus) -> Option<ProcessState> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(ProcessState::Exited(code)),
+            WaitStatus::Signaled(_, signal, core) => Some(ProcessState::Signaled(signal, core)),
+            WaitStatus::Stopped(_, signal) => Some(ProcessState::Stopped(signal)),
+            WaitStatus::Continued(_) => Some(ProcessState::Running),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: Pid,
+    pub state: ProcessState,
+}
+
+/// A pipeline or background list, run as one process group when job
+/// control is on.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// The `n` of `%n`, or 0 until the job is put in the table.
+    pub id: usize,
+    pub pgid: Pid,
+    pub processes: Vec<Process>,
+    /// The command as written, for `jobs` and status reports.
+    pub command: String,
+    /// Whether the job's latest state change has been reported.
+    pub notified: bool,
+}
+
+impl Job {
+    /// A job of processes that have just been started.
+    pub fn new(pgid: Pid, pids: Vec<Pid>, command: String) -> Self {
+        Job {
+            id: 0,
+            pgid,
+            processes: pids
+                .into_iter()
+                .map(|pid| Process {
+                    pid,
+                    state: ProcessState::Running,
+                })
+                .collect(),
+            command,
+            notified: true,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.processes.iter().all(|p| {
+            matches!(
+                p.state,
+                ProcessState::Exited(_) | ProcessState::Signaled(..)
+            )
+        })
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.is_done()
+            && self
+                .processes
+                .iter()
+                .all(|p| !matches!(p.state, ProcessState::Running))
+    }
+
+    /// The status of the last process in the pipeline.
+    pub fn status(&self) -> i32 {
+        self.processes.last().map_or(0, |p| p.state.status())
+    }
+
+    /// The status of the last process that failed, or 0 if none did, for
+    /// `set -o pipefail`.
+    pub fn failed_status(&self) -> i32 {
+        self.processes
+            .iter()
+            .rev()
+            .map(|p| p.state.status())
+            .find(|&status| status != 0)
+            .unwrap_or(0)
+    }
+
+    /// Waits until every process has finished or one has stopped. When one
+    /// stops, the others, having had the same signal, are checked without
+    /// blocking.
+    pub fn wait(&mut self) {
+        let mut flags = WaitPidFlag::WUNTRACED;
+        for process in &mut self.processes {
+            if process.state != ProcessState::Running {
+                continue;
+            }
+            match waitpid(process.pid, Some(flags)) {
+                Ok(status) => {
+                    if let Some(state) = ProcessState::from_wait(status) {
+                        process.state = state;
+                    }
+                }
+                // Already reaped, as happens for a process substitution's
+                // child that a command used as its own
+                Err(_) => process.state = ProcessState::Exited(0),
+            }
+            if matches!(process.state, ProcessState::Stopped(_)) {
+                flags |= WaitPidFlag::WNOHANG;
+            }
+        }
+    }
+
+    /// Collects state changes without blocking. Returns whether any
+    /// happened.
+    pub fn poll(&mut self) -> bool {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+        let mut changed = false;
+        for process in &mut self.processes {
+            if matches!(
+                process.state,
+                ProcessState::Exited(_) | ProcessState::Signaled(..)
+            ) {
+                continue;
+            }
+            // An error means it is not our child, as in a subshell, which
+            // can't tell what became of it
+            let state = waitpid(process.pid, Some(flags))
+                .ok()
+                .and_then(ProcessState::from_wait);
+            if let Some(state) = state {
+                if state != process.state {
+                    process.state = state;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Marks stopped processes as running again, after a `SIGCONT`.
+    pub fn mark_running(&mut self) {
+        for process in &mut self.processes {
+            if matches!(process.state, ProcessState::Stopped(_)) {
+                process.state = ProcessState::Running;
+            }
+        }
+    }
+
+    /// Sends a signal to every process in the job, or with `None` only
+    /// checks that they exist.
+    pub fn signal(&self, signal: Option<Signal>, job_control: bool) -> nix::Result<()> {
+        if job_control {
+            return signal::killpg(self.pgid, signal);
+        }
+        for process in &self.processes {
+            signal::kill(process.pid, signal)?;
+        }
+        Ok(())
+    }
+
+    /// The state column of `jobs`: `Running`, `Stopped`, `Done`, `Exit 2`
+    /// or a signal's description.
+    pub fn state_text(&self) -> String {
+        if self.is_stopped() {
+            return "Stopped".to_string();
+        }
+        if !self.is_done() {
+            return "Running".to_string();
+        }
+        match self.processes.last().map(|p| p.state) {
+            Some(ProcessState::Exited(0)) | None => "Done".to_string(),
+            Some(ProcessState::Exited(code)) => format!("Exit {}", code),
+            Some(ProcessState::Signaled(signal, core)) => death_message(signal, core),
+            Some(state) => format!("{:?}", state),
+        }
+    }
+}
+
+/// Jobs by number, with the current (`%+`) and previous (`%-`) jobs.
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    /// Job numbers from least to most recently started, stopped or
+    /// resumed; the last is the current job.
+    recent: Vec<usize>,
+}
+
+impl JobTable {
+    /// Adds a job, numbering it if it doesn't have a number yet, and makes
+    /// it the current job. Returns its number.
+    pub fn insert(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        let at = self.jobs.partition_point(|job| job.id < id);
+        self.jobs.insert(at, job);
+        self.touch(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recent.retain(|&recent| recent != id);
+        let at = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(at))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.jobs.iter().map(|job| job.id).collect()
+    }
+
+    /// Collects state changes of all jobs without blocking, marking the
+    /// jobs that changed as not yet reported.
+    pub fn poll(&mut self) {
+        for job in &mut self.jobs {
+            if job.poll() {
+                job.notified = false;
+            }
+        }
+    }
+
+    /// Makes a job the current one.
+    pub fn touch(&mut self, id: usize) {
+        self.recent.retain(|&recent| recent != id);
+        self.recent.push(id);
+    }
+
+    /// `+` for the current job, `-` for the previous one, else a space.
+    pub fn marker(&self, id: usize) -> char {
+        match self.recent.iter().rev().position(|&recent| recent == id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// The job with a process `pid`, or whose group is `pid`.
+    pub fn find_pid(&self, pid: Pid) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.pgid == pid || job.processes.iter().any(|p| p.pid == pid))
+            .map(|job| job.id)
+    }
+
+    /// Resolves a job spec: `%n`, `%+`, `%%` or `%` for the current job,
+    /// `%-` for the previous one, `%name` for the job whose command starts
+    /// with `name` and `%?text` for the one containing `text`.
+    pub fn resolve(&self, spec: &str) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+        let body = spec.strip_prefix('%').unwrap_or(spec);
+        let found = match body {
+            "" | "+" | "%" => self.recent.last().copied(),
+            "-" => self.recent.iter().rev().nth(1).copied(),
+            _ => {
+                if let Ok(id) = body.parse::<usize>() {
+                    return self.get(id).map(|job| job.id).ok_or_else(no_such_job);
+                }
+                let matching: Vec<usize> = match body.strip_prefix('?') {
+                    Some(text) => self.matching(|command| command.contains(text)),
+                    None => self.matching(|command| command.starts_with(body)),
+                };
+                if matching.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec));
+                }
+                matching.first().copied()
+            }
+        };
+        found.ok_or_else(no_such_job)
+    }
+
+    fn matching(&self, pred: impl Fn(&str) -> bool) -> Vec<usize> {
+        self.jobs
+            .iter()
+            .filter(|job| pred(&job.command))
+            .map(|job| job.id)
+            .collect()
+    }
+
+    /// A `jobs` line: `[1]+  Running                 sleep 10 &`, with the
+    /// process id after the marker when `pid` is set.
+    pub fn format(&self, job: &Job, pid: bool) -> String {
+        let mut command = job.command.clone();
+        if !job.is_done() && !job.is_stopped() {
+            command.push_str(" &");
+        }
+        let pid = if pid {
+            format!(" {} ", job.pgid)
+        } else {
+            " ".to_string()
+        };
+        format!(
+            "[{}]{} {}{:<24}{}",
+            job.id,
+            self.marker(job.id),
+            pid,
+            job.state_text(),
+            command
+        )
+    }
+}
+
+/// Makes the shell the foreground process group of its terminal, in a
+/// group of its own, ignoring the signals that would stop it. Returns
+/// false if standard input is not a terminal.
+pub fn enable() -> bool {
+    let stdin = io::stdin();
+    let terminal = stdin.as_fd();
+    if !unistd::isatty(terminal).unwrap_or(false) {
+        return false;
+    }
+    // Wait to be put in the foreground if started in the background
+    loop {
+        let group = unistd::getpgrp();
+        match unistd::tcgetpgrp(terminal) {
+            Ok(foreground) if foreground == group => break,
+            Ok(_) => {
+                let _ = signal::killpg(group, Signal::SIGTTIN);
+            }
+            Err(_) => return false,
+        }
+    }
+    for signal in INTERACTIVE_SIGNALS {
+        // SAFETY: ignoring a signal installs no handler code.
+        let _ = unsafe { signal::signal(signal, SigHandler::SigIgn) };
+    }
+    let shell = unistd::getpid();
+    // Fails harmlessly if the shell already leads its session
+    let _ = unistd::setpgid(shell, shell);
+    let _ = unistd::tcsetpgrp(terminal, shell);
+    true
+}
+
+/// Hands the terminal to a process group.
+pub fn give_terminal(pgid: Pid) {
+    let _ = unistd::tcsetpgrp(io::stdin().as_fd(), pgid);
+}
+
+/// Called in a process just forked or about to exec: joins process group
+/// `pgid`, or a new one of its own when it is 0, takes the terminal for a
+/// foreground job, and gets back the default handling of the signals the
+/// shell ignores. Only async-signal-safe calls, so it can run before exec.
+pub fn setup_child(pgid: libc::pid_t, foreground: bool) {
+    // SAFETY: these calls only change process attributes.
+    unsafe {
+        let pid = libc::getpid();
+        let pgid = if pgid == 0 { pid } else { pgid };
+        libc::setpgid(0, pgid);
+        if foreground {
+            libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+        }
+    }
+    reset_signals();
+}
+
+/// Whether an interactive shell ignores `signal` unless trapped.
+pub fn ignored_when_interactive(signal: Signal) -> bool {
+    INTERACTIVE_SIGNALS.contains(&signal)
+}
+
+/// Restores the default handling of the signals the shell ignores.
+pub fn reset_signals() {
+    for signal in INTERACTIVE_SIGNALS {
+        // SAFETY: restoring the default disposition installs no handler code.
+        unsafe { libc::signal(signal as libc::c_int, libc::SIG_DFL) };
+    }
+}
+
+/// Restores the default handling of SIGPIPE, which the Rust runtime
+/// ignores, so a forked child writing to a closed pipe dies quietly.
+pub fn reset_sigpipe() {
+    // SAFETY: restoring the default disposition installs no handler code.
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+}
+
+/// Parses a signal given by number, or by name in any case with or without
+/// `SIG`.
+pub fn parse_signal(spec: &str) -> Option<Signal> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let name = spec.to_ascii_uppercase();
+    if name.starts_with("SIG") {
+        name.parse().ok()
+    } else {
+        format!("SIG{}", name).parse().ok()
+    }
+}
+
+/// A signal's name without `SIG`, as `kill -l` prints it.
+pub fn signal_name(signal: Signal) -> &'static str {
+    &signal.as_str()[3..]
+}
+
+/// What to say about a process killed by `signal`, such as
+/// "Segmentation fault (core dumped)".
+pub fn death_message(signal: Signal, core: bool) -> String {
+    let mut text = signal_description(signal);
+    if core {
+        text.push_str(" (core dumped)");
+    }
+    text
+}
+
+/// A signal's description as `strsignal` gives it, such as "Terminated".
+pub fn signal_description(signal: Signal) -> String {
+    // SAFETY: strsignal returns a pointer to a NUL-terminated string that
+    // stays valid until the next call, and it is copied straight away.
+    unsafe {
+        let text = libc::strsignal(signal as libc::c_int);
+        if text.is_null() {
+            return format!("{:?}", signal);
+        }
+        CStr::from_ptr(text).to_string_lossy().into_owned()
+    }
+}
@@ -0,0 +1,1082 @@
+use crate::ast::{ParamExpansion, ParamOp, ReplaceMode, Span, Word, WordPart};
+use crate::parser::{self, Aliases, ParseError};
+use crate::vars::is_valid_name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Pipe,
+    OrIf,
+    AndIf,
+    Semi,
+    /// `;;`, ending a `case` item.
+    DSemi,
+    /// `;&`, falling through to the next `case` item's body.
+    SemiAnd,
+    /// `;;&`, going on to test the next `case` item's patterns.
+    DSemiAnd,
+    Amp,
+    LParen,
+    RParen,
+    Less,
+    Great,
+    DGreat,
+    /// `>|`, which overwrites a file even with `set -o noclobber`.
+    Clobber,
+    /// `<<`, a here-document.
+    DLess,
+    /// `<<-`, a here-document with leading tabs stripped.
+    DLessDash,
+    /// `<<<`, a here-string.
+    TLess,
+    /// `<&`
+    LessAnd,
+    /// `>&`
+    GreatAnd,
+    /// `<>`
+    LessGreat,
+    /// `&>`
+    AndGreat,
+    /// `&>>`
+    AndDGreat,
+}
+
+impl Op {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Op::Pipe => "|",
+            Op::OrIf => "||",
+            Op::AndIf => "&&",
+            Op::Semi => ";",
+            Op::DSemi => ";;",
+            Op::SemiAnd => ";&",
+            Op::DSemiAnd => ";;&",
+            Op::Amp => "&",
+            Op::LParen => "(",
+            Op::RParen => ")",
+            Op::Less => "<",
+            Op::Great => ">",
+            Op::DGreat => ">>",
+            Op::Clobber => ">|",
+            Op::DLess => "<<",
+            Op::DLessDash => "<<-",
+            Op::TLess => "<<<",
+            Op::LessAnd => "<&",
+            Op::GreatAnd => ">&",
+            Op::LessGreat => "<>",
+            Op::AndGreat => "&>",
+            Op::AndDGreat => "&>>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Word(Word),
+    /// A file descriptor number written directly before a redirection operator, as in `2>`.
+    IoNumber(u32),
+    /// `{name}` written directly before a redirection operator, which
+    /// allocates a descriptor and stores its number in the variable.
+    IoName(String),
+    Op(Op),
+    Newline,
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    /// How the token is shown in `syntax error near unexpected token` messages.
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            TokenKind::Word(word) => word.unquoted(),
+            TokenKind::IoNumber(n) => n.to_string(),
+            TokenKind::IoName(name) => format!("{{{}}}", name),
+            TokenKind::Op(op) => op.as_str().to_string(),
+            TokenKind::Newline => "newline".to_string(),
+            TokenKind::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+fn is_metachar(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\t' | '\n' | '|' | '&' | ';' | '(' | ')' | '<' | '>'
+    )
+}
+
+pub struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Passed on to the parser for command substitutions.
+    aliases: &'a Aliases,
+    /// Line of the script that `src` starts on.
+    line: usize,
+    /// Where the input resumes after the here-document bodies already read
+    /// for the current line, which the next newline skips to.
+    heredoc_resume: Option<usize>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str, aliases: &'a Aliases) -> Self {
+        Self::at(src, 0, aliases)
+    }
+
+    /// A lexer that starts reading at byte offset `pos` of `src`.
+    pub fn at(src: &'a str, pos: usize, aliases: &'a Aliases) -> Self {
+        Self {
+            src,
+            pos,
+            aliases,
+            line: 1,
+            heredoc_resume: None,
+        }
+    }
+
+    /// Numbers the lines of `src` from `line` rather than 1.
+    pub fn starting_on_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+
+    /// A lexer reading on from the current position, for the body of a
+    /// substitution.
+    fn nested(&self) -> Self {
+        Lexer::at(self.src, self.pos, self.aliases).starting_on_line(self.line)
+    }
+
+    /// The line of the script that byte offset `pos` of the input is on.
+    pub fn line_at(&self, pos: usize) -> usize {
+        self.line + self.src[..pos].matches('\n').count()
+    }
+
+    /// Byte offset of the next unread character.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// The source text of `span`, or nothing if it lies outside the input.
+    pub fn text(&self, span: Span) -> &'a str {
+        self.src.get(span.start..span.end).unwrap_or("")
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn peek_char_at(&self, n: usize) -> Option<char> {
+        self.src[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skips blanks, escaped newlines and comments, stopping at a newline.
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek_char() {
+                Some(' ' | '\t') => {
+                    self.bump();
+                }
+                Some('\\') if self.peek_char_at(1) == Some('\n') => {
+                    self.bump();
+                    self.bump();
+                }
+                Some('#') => {
+                    while !matches!(self.peek_char(), None | Some('\n')) {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Token, ParseError> {
+        self.skip_blanks();
+        let start = self.pos;
+        let Some(c) = self.peek_char() else {
+            return Ok(Token {
+                kind: TokenKind::Eof,
+                span: Span::new(start, start),
+            });
+        };
+
+        let kind = if c == '\n' {
+            self.bump();
+            if let Some(resume) = self.heredoc_resume.take() {
+                self.pos = resume;
+            }
+            TokenKind::Newline
+        } else if is_metachar(c) && !self.at_process_sub() {
+            TokenKind::Op(self.read_op())
+        } else {
+            let word = self.read_word()?;
+            match (word.as_literal(), self.peek_char()) {
+                (Some(digits), Some('<' | '>')) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                    match digits.parse() {
+                        Ok(fd) => TokenKind::IoNumber(fd),
+                        Err(_) => TokenKind::Word(word),
+                    }
+                }
+                (Some(text), Some('<' | '>')) => match text
+                    .strip_prefix('{')
+                    .and_then(|text| text.strip_suffix('}'))
+                {
+                    Some(name) if is_valid_name(name) => TokenKind::IoName(name.to_string()),
+                    _ => TokenKind::Word(word),
+                },
+                _ => TokenKind::Word(word),
+            }
+        };
+
+        Ok(Token {
+            kind,
+            span: Span::new(start, self.pos),
+        })
+    }
+
+    fn read_op(&mut self) -> Op {
+        let c = self.bump().unwrap_or_default();
+        let next = self.peek_char();
+        let (op, doubled) = match (c, next) {
+            ('|', Some('|')) => (Op::OrIf, true),
+            ('|', _) => (Op::Pipe, false),
+            ('&', Some('&')) => (Op::AndIf, true),
+            ('&', Some('>')) => {
+                self.bump();
+                if self.peek_char() == Some('>') {
+                    (Op::AndDGreat, true)
+                } else {
+                    (Op::AndGreat, false)
+                }
+            }
+            ('&', _) => (Op::Amp, false),
+            (';', Some(';')) => {
+                self.bump();
+                if self.peek_char() == Some('&') {
+                    (Op::DSemiAnd, true)
+                } else {
+                    (Op::DSemi, false)
+                }
+            }
+            (';', Some('&')) => (Op::SemiAnd, true),
+            (';', _) => (Op::Semi, false),
+            ('(', _) => (Op::LParen, false),
+            (')', _) => (Op::RParen, false),
+            ('<', Some('<')) => {
+                self.bump();
+                match self.peek_char() {
+                    Some('<') => (Op::TLess, true),
+                    Some('-') => (Op::DLessDash, true),
+                    _ => (Op::DLess, false),
+                }
+            }
+            ('<', Some('&')) => (Op::LessAnd, true),
+            ('<', Some('>')) => (Op::LessGreat, true),
+            ('<', _) => (Op::Less, false),
+            ('>', Some('>')) => (Op::DGreat, true),
+            ('>', Some('&')) => (Op::GreatAnd, true),
+            ('>', Some('|')) => (Op::Clobber, true),
+            _ => (Op::Great, false),
+        };
+        if doubled {
+            self.bump();
+        }
+        op
+    }
+
+    fn read_word(&mut self) -> Result<Word, ParseError> {
+        let start = self.pos;
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek_char() {
+            if self.at_process_sub() {
+                flush_literal(&mut literal, &mut parts);
+                parts.push(self.read_process_sub()?);
+                continue;
+            }
+            if is_metachar(c) {
+                break;
+            }
+            match c {
+                '\'' => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::Quoted(self.read_single_quoted()?));
+                }
+                '"' => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::DoubleQuoted(self.read_double_quoted()?));
+                }
+                '`' => {
+                    flush_literal(&mut literal, &mut parts);
+                    self.bump();
+                    parts.push(self.read_backquoted(false)?);
+                }
+                '$' => {
+                    self.bump();
+                    match self.read_dollar()? {
+                        Some(part) => {
+                            flush_literal(&mut literal, &mut parts);
+                            parts.push(part);
+                        }
+                        None => literal.push('$'),
+                    }
+                }
+                '\\' => {
+                    self.bump();
+                    match self.bump() {
+                        // Line continuation
+                        Some('\n') => {}
+                        Some(c) => {
+                            flush_literal(&mut literal, &mut parts);
+                            parts.push(WordPart::Quoted(c.to_string()));
+                        }
+                        None => {
+                            return Err(ParseError::incomplete(
+                                "syntax error: unexpected end of file",
+                                Span::new(start, self.pos),
+                            ))
+                        }
+                    }
+                }
+                _ => {
+                    literal.push(c);
+                    self.bump();
+                }
+            }
+        }
+        flush_literal(&mut literal, &mut parts);
+
+        Ok(Word {
+            parts,
+            span: Span::new(start, self.pos),
+        })
+    }
+
+    /// Reads a legacy `` `...` `` command substitution. Inside it a backslash
+    /// only escapes `$`, `` ` ``, `\` and, within double quotes, `"`.
+    /// The opening backquote has already been consumed.
+    fn read_backquoted(&mut self, in_double_quotes: bool) -> Result<WordPart, ParseError> {
+        let quote_start = self.pos - 1;
+        let mut inner = String::new();
+        loop {
+            match self.bump() {
+                Some('`') => break,
+                Some('\\') => match self.peek_char() {
+                    Some(c) if matches!(c, '$' | '`' | '\\') || (in_double_quotes && c == '"') => {
+                        inner.push(c);
+                        self.bump();
+                    }
+                    _ => inner.push('\\'),
+                },
+                Some(c) => inner.push(c),
+                None => return Err(unterminated('`', quote_start, self.pos)),
+            }
+        }
+
+        let line = self.line_at(quote_start);
+        let list = parser::parse_from_line(&inner, line, self.aliases).map_err(|mut err| {
+            // Positions inside the backquotes don't map back onto this source
+            err.span = Span::new(quote_start, self.pos);
+            err
+        })?;
+        Ok(WordPart::CommandSub(list))
+    }
+
+    /// Whether `<(` or `>(` comes next, which starts a process substitution
+    /// rather than a redirection.
+    fn at_process_sub(&self) -> bool {
+        matches!(self.peek_char(), Some('<' | '>')) && self.peek_char_at(1) == Some('(')
+    }
+
+    fn read_process_sub(&mut self) -> Result<WordPart, ParseError> {
+        let output = self.bump() == Some('>');
+        self.bump();
+        let (list, end) = parser::parse_command_substitution(self.nested(), self.aliases)?;
+        self.pos = end;
+        Ok(WordPart::ProcessSub { list, output })
+    }
+
+    fn read_single_quoted(&mut self) -> Result<String, ParseError> {
+        let quote_start = self.pos;
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(text),
+                Some(c) => text.push(c),
+                None => return Err(unterminated('\'', quote_start, self.pos)),
+            }
+        }
+    }
+
+    /// Reads the expansion following a `$`: a name, a special parameter such
+    /// as `?`, a `{...}` form or a `(...)` command substitution. Returns
+    /// `None` when the `$` is just a literal dollar sign.
+    fn read_dollar(&mut self) -> Result<Option<WordPart>, ParseError> {
+        let start = self.pos - 1;
+        match self.peek_char() {
+            Some('(') => {
+                self.bump();
+                if let Some(expr) = self.read_arithmetic_command()? {
+                    return Ok(Some(WordPart::Arithmetic(expr)));
+                }
+                let (list, end) = parser::parse_command_substitution(self.nested(), self.aliases)?;
+                self.pos = end;
+                Ok(Some(WordPart::CommandSub(list)))
+            }
+            Some('{') => {
+                self.bump();
+                let param = self.read_braced_param(start)?;
+                Ok(Some(WordPart::Param(param)))
+            }
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = self.peek_char() {
+                    if c != '_' && !c.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    name.push(c);
+                    self.bump();
+                }
+                Ok(Some(WordPart::Param(plain_param(name))))
+            }
+            Some(c) if c.is_ascii_digit() || is_special_param(c) => {
+                self.bump();
+                Ok(Some(WordPart::Param(plain_param(c.to_string()))))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads `(expression))` when the lexer is just past an opening `(` that is
+    /// followed by another, as in `$((...))` or a `((...))` command. Returns
+    /// `None`, without consuming anything, if the parentheses turn out to
+    /// close one at a time, as in `((cd /tmp; ls) )`.
+    pub fn read_arithmetic_command(&mut self) -> Result<Option<Word>, ParseError> {
+        if self.peek_char() != Some('(') {
+            return Ok(None);
+        }
+        let open = self.pos - 1;
+        self.bump();
+        let start = self.pos;
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut depth = 0;
+
+        loop {
+            let Some(c) = self.peek_char() else {
+                return Err(unterminated(')', open, self.pos));
+            };
+            match c {
+                '"' => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::DoubleQuoted(self.read_double_quoted()?));
+                    continue;
+                }
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' => {
+                    let end = self.pos;
+                    self.bump();
+                    if self.bump() != Some(')') {
+                        self.pos = open + 1;
+                        return Ok(None);
+                    }
+                    flush_literal(&mut literal, &mut parts);
+                    return Ok(Some(Word {
+                        parts,
+                        span: Span::new(start, end),
+                    }));
+                }
+                _ => {}
+            }
+
+            self.bump();
+            match c {
+                '\\' => match self.bump() {
+                    Some('\n') => {}
+                    Some(c) => {
+                        flush_literal(&mut literal, &mut parts);
+                        parts.push(WordPart::Quoted(c.to_string()));
+                    }
+                    None => literal.push('\\'),
+                },
+                '$' => match self.read_dollar()? {
+                    Some(part) => {
+                        flush_literal(&mut literal, &mut parts);
+                        parts.push(part);
+                    }
+                    None => literal.push('$'),
+                },
+                '`' => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(self.read_backquoted(false)?);
+                }
+                c => literal.push(c),
+            }
+        }
+    }
+
+    /// Reads the inside of `${...}` after the opening brace.
+    fn read_braced_param(&mut self, start: usize) -> Result<ParamExpansion, ParseError> {
+        // `${#name}` is a length, but `${#}` is the parameter `#`
+        let length =
+            self.peek_char() == Some('#') && !matches!(self.peek_char_at(1), Some('}') | None);
+        if length {
+            self.bump();
+        }
+
+        let mut name = String::new();
+        match self.peek_char() {
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+                while let Some(c) = self
+                    .peek_char()
+                    .filter(|&c| c == '_' || c.is_ascii_alphanumeric())
+                {
+                    name.push(c);
+                    self.bump();
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                while let Some(c) = self.peek_char().filter(char::is_ascii_digit) {
+                    name.push(c);
+                    self.bump();
+                }
+            }
+            Some(c) if is_special_param(c) => {
+                name.push(c);
+                self.bump();
+            }
+            None => return Err(unterminated('}', start, self.pos)),
+            _ => {}
+        }
+
+        let bad_substitution = |lexer: &mut Self| {
+            // Skip to the closing brace so the message can show the whole thing
+            while !matches!(lexer.peek_char(), Some('}') | None) {
+                lexer.bump();
+            }
+            lexer.bump();
+            ParseError::new(
+                format!("{}: bad substitution", &lexer.src[start..lexer.pos]),
+                Span::new(start, lexer.pos),
+            )
+        };
+        if name.is_empty() {
+            return Err(bad_substitution(self));
+        }
+        if length {
+            if self.bump() != Some('}') {
+                return Err(bad_substitution(self));
+            }
+            return Ok(ParamExpansion {
+                name,
+                op: ParamOp::Length,
+            });
+        }
+
+        let Some(c) = self.bump() else {
+            return Err(unterminated('}', start, self.pos));
+        };
+        let op = match c {
+            '}' => return Ok(plain_param(name)),
+            ':' if matches!(self.peek_char(), Some('-' | '=' | '?' | '+')) => {
+                let op = self.bump().unwrap_or_default();
+                let word = self.read_param_word(&['}'], start)?;
+                word_op(op, true, word)
+            }
+            '-' | '=' | '?' | '+' => {
+                let word = self.read_param_word(&['}'], start)?;
+                word_op(c, false, word)
+            }
+            ':' => {
+                let offset = self.read_param_word(&[':', '}'], start)?;
+                let length = if self.peek_char() == Some(':') {
+                    self.bump();
+                    Some(self.read_param_word(&['}'], start)?)
+                } else {
+                    None
+                };
+                ParamOp::Substring { offset, length }
+            }
+            '#' | '%' => {
+                let longest = self.peek_char() == Some(c);
+                if longest {
+                    self.bump();
+                }
+                let pattern = self.read_param_word(&['}'], start)?;
+                if c == '#' {
+                    ParamOp::RemovePrefix { longest, pattern }
+                } else {
+                    ParamOp::RemoveSuffix { longest, pattern }
+                }
+            }
+            '/' => {
+                let mode = match self.peek_char() {
+                    Some('/') => ReplaceMode::All,
+                    Some('#') => ReplaceMode::Prefix,
+                    Some('%') => ReplaceMode::Suffix,
+                    _ => ReplaceMode::First,
+                };
+                if mode != ReplaceMode::First {
+                    self.bump();
+                }
+                let pattern = self.read_param_word(&['/', '}'], start)?;
+                let replacement = if self.peek_char() == Some('/') {
+                    self.bump();
+                    self.read_param_word(&['}'], start)?
+                } else {
+                    Word::default()
+                };
+                ParamOp::Replace {
+                    mode,
+                    pattern,
+                    replacement,
+                }
+            }
+            '^' | ',' => {
+                let all = self.peek_char() == Some(c);
+                if all {
+                    self.bump();
+                }
+                let pattern = self.read_param_word(&['}'], start)?;
+                ParamOp::Case {
+                    upper: c == '^',
+                    all,
+                    pattern: (!pattern.parts.is_empty()).then_some(pattern),
+                }
+            }
+            _ => return Err(bad_substitution(self)),
+        };
+
+        // Every operator's word stops at the closing brace
+        self.bump();
+        Ok(ParamExpansion { name, op })
+    }
+
+    /// Reads the word operand of a `${...}` operator up to an unquoted,
+    /// unnested character in `stops`, which is left unconsumed.
+    fn read_param_word(&mut self, stops: &[char], start: usize) -> Result<Word, ParseError> {
+        let word_start = self.pos;
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut depth = 0;
+
+        loop {
+            let Some(c) = self.peek_char() else {
+                return Err(unterminated('}', start, self.pos));
+            };
+            if depth == 0 && stops.contains(&c) {
+                break;
+            }
+            match c {
+                '\\' => {
+                    self.bump();
+                    match self.bump() {
+                        Some('\n') => {}
+                        Some(c) => {
+                            flush_literal(&mut literal, &mut parts);
+                            parts.push(WordPart::Quoted(c.to_string()));
+                        }
+                        None => return Err(unterminated('}', start, self.pos)),
+                    }
+                }
+                '\'' => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::Quoted(self.read_single_quoted()?));
+                }
+                '"' => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::DoubleQuoted(self.read_double_quoted()?));
+                }
+                '`' => {
+                    flush_literal(&mut literal, &mut parts);
+                    self.bump();
+                    parts.push(self.read_backquoted(false)?);
+                }
+                '$' => {
+                    self.bump();
+                    match self.read_dollar()? {
+                        Some(part) => {
+                            flush_literal(&mut literal, &mut parts);
+                            parts.push(part);
+                        }
+                        None => literal.push('$'),
+                    }
+                }
+                _ => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    literal.push(c);
+                    self.bump();
+                }
+            }
+        }
+        flush_literal(&mut literal, &mut parts);
+
+        Ok(Word {
+            parts,
+            span: Span::new(word_start, self.pos),
+        })
+    }
+
+    fn read_double_quoted(&mut self) -> Result<Vec<WordPart>, ParseError> {
+        let quote_start = self.pos;
+        self.bump();
+        self.read_expandable(Some(quote_start))
+    }
+
+    /// Reads the body of an unquoted here-document, which expands like
+    /// double-quoted text except that `"` is not special.
+    pub fn read_heredoc_body(&mut self) -> Result<Vec<WordPart>, ParseError> {
+        self.read_expandable(None)
+    }
+
+    /// Reads text in which only `$`, backquotes and backslashes are special,
+    /// up to the closing `"` of the quotes opened at `quote_start`, or to
+    /// the end of the input if there are none.
+    fn read_expandable(&mut self, quote_start: Option<usize>) -> Result<Vec<WordPart>, ParseError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+
+        loop {
+            match self.bump() {
+                Some('"') if quote_start.is_some() => break,
+                Some('\\') => match self.peek_char() {
+                    // Inside double quotes a backslash only escapes these
+                    Some('"') if quote_start.is_some() => {
+                        literal.push(self.bump().unwrap_or_default())
+                    }
+                    Some('\\' | '$' | '`') => literal.push(self.bump().unwrap_or_default()),
+                    Some('\n') => {
+                        self.bump();
+                    }
+                    _ => literal.push('\\'),
+                },
+                Some('$') => match self.read_dollar()? {
+                    Some(part) => {
+                        flush_literal(&mut literal, &mut parts);
+                        parts.push(part);
+                    }
+                    None => literal.push('$'),
+                },
+                Some('`') => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(self.read_backquoted(true)?);
+                }
+                Some(c) => literal.push(c),
+                None => match quote_start {
+                    Some(start) => return Err(unterminated('"', start, self.pos)),
+                    None => break,
+                },
+            }
+        }
+        flush_literal(&mut literal, &mut parts);
+        Ok(parts)
+    }
+
+    /// Reads the body of a here-document whose operator is on the current
+    /// line: the lines after it, and after any earlier here-document on it,
+    /// up to one holding just `delimiter`. `strip_tabs`, for `<<-`, removes
+    /// leading tabs from each line first. `start` is where the operator is.
+    pub fn read_heredoc(
+        &mut self,
+        delimiter: &str,
+        strip_tabs: bool,
+        start: usize,
+    ) -> Result<String, ParseError> {
+        let unterminated = || {
+            ParseError::incomplete(
+                format!(
+                    "here-document delimited by end-of-file (wanted `{}')",
+                    delimiter
+                ),
+                Span::new(start, self.src.len()),
+            )
+        };
+        let mut pos = match self.heredoc_resume {
+            Some(pos) => pos,
+            None => match self.src[self.pos..].find('\n') {
+                Some(offset) => self.pos + offset + 1,
+                None => return Err(unterminated()),
+            },
+        };
+
+        let mut body = String::new();
+        while pos < self.src.len() {
+            let end = self.src[pos..]
+                .find('\n')
+                .map_or(self.src.len(), |offset| pos + offset + 1);
+            let mut line = &self.src[pos..end];
+            pos = end;
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
+            }
+            if line.strip_suffix('\n').unwrap_or(line) == delimiter {
+                self.heredoc_resume = Some(pos);
+                return Ok(body);
+            }
+            body.push_str(line);
+        }
+        Err(unterminated())
+    }
+}
+
+fn plain_param(name: String) -> ParamExpansion {
+    ParamExpansion {
+        name,
+        op: ParamOp::Plain,
+    }
+}
+
+/// Builds the operator for `${v-word}`, `${v=word}`, `${v?word}` or `${v+word}`.
+fn word_op(op: char, colon: bool, word: Word) -> ParamOp {
+    match op {
+        '-' => ParamOp::Default { colon, word },
+        '=' => ParamOp::Assign { colon, word },
+        '?' => ParamOp::Error { colon, word },
+        _ => ParamOp::Alternative { colon, word },
+    }
+}
+
+/// Parameters with a one-character name that is not a valid variable name.
+fn is_special_param(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '@' | '*' | '!' | '-')
+}
+
+fn flush_literal(literal: &mut String, parts: &mut Vec<WordPart>) {
+    if !literal.is_empty() {
+        parts.push(WordPart::Literal(std::mem::take(literal)));
+    }
+}
+
+fn unterminated(quote: char, start: usize, end: usize) -> ParseError {
+    ParseError::incomplete(
+        format!("unexpected EOF while looking for matching `{}'", quote),
+        Span::new(start, end),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        let aliases = Aliases::new();
+        let mut lexer = Lexer::new(src, &aliases);
+        let mut kinds = Vec::new();
+        loop {
+            let token = lexer.next_token().unwrap();
+            if token.kind == TokenKind::Eof {
+                return kinds;
+            }
+            kinds.push(token.kind);
+        }
+    }
+
+    fn ops(src: &str) -> Vec<Op> {
+        kinds(src)
+            .into_iter()
+            .map(|kind| match kind {
+                TokenKind::Op(op) => op,
+                other => panic!("expected an operator, got {:?}", other),
+            })
+            .collect()
+    }
+
+    fn word(src: &str) -> Vec<WordPart> {
+        match kinds(src).as_slice() {
+            [TokenKind::Word(word)] => word.parts.clone(),
+            other => panic!("expected one word, got {:?}", other),
+        }
+    }
+
+    fn assert_incomplete(src: &str) {
+        let aliases = Aliases::new();
+        let err = Lexer::new(src, &aliases).next_token().unwrap_err();
+        assert!(err.incomplete, "{:?} should be incomplete", src);
+    }
+
+    fn literal(text: &str) -> WordPart {
+        WordPart::Literal(text.to_string())
+    }
+
+    fn quoted(text: &str) -> WordPart {
+        WordPart::Quoted(text.to_string())
+    }
+
+    fn param(name: &str) -> WordPart {
+        WordPart::Param(ParamExpansion {
+            name: name.to_string(),
+            op: ParamOp::Plain,
+        })
+    }
+
+    #[test]
+    fn operators_take_the_longest_match() {
+        assert_eq!(
+            ops("| || && ; & ( ) < > >>"),
+            [
+                Op::Pipe,
+                Op::OrIf,
+                Op::AndIf,
+                Op::Semi,
+                Op::Amp,
+                Op::LParen,
+                Op::RParen,
+                Op::Less,
+                Op::Great,
+                Op::DGreat,
+            ]
+        );
+        assert_eq!(ops("&&&"), [Op::AndIf, Op::Amp]);
+    }
+
+    #[test]
+    fn io_numbers_only_directly_before_redirections() {
+        assert_eq!(kinds("2>x")[0], TokenKind::IoNumber(2));
+        assert!(matches!(kinds("2 >x")[0], TokenKind::Word(_)));
+        assert!(matches!(kinds("a2>x")[0], TokenKind::Word(_)));
+    }
+
+    #[test]
+    fn blanks_comments_and_newlines() {
+        let tokens = kinds("a  b # comment\nc \\\nd");
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[2], TokenKind::Newline);
+        assert!(matches!(&tokens[4], TokenKind::Word(w) if w.unquoted() == "d"));
+        // `#` only starts a comment at the start of a word
+        assert_eq!(word("a#b"), [literal("a#b")]);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(word("'a b'"), [quoted("a b")]);
+        assert_eq!(word(r"a\ b"), [literal("a"), quoted(" "), literal("b")]);
+        assert_eq!(
+            word(r#""x $y \$ \z""#),
+            [WordPart::DoubleQuoted(vec![
+                literal("x "),
+                param("y"),
+                literal(r" $ \z"),
+            ])]
+        );
+        assert_eq!(word("''"), [quoted("")]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_incomplete() {
+        assert_incomplete("'abc");
+        assert_incomplete("\"abc");
+        assert_incomplete("${a");
+    }
+
+    #[test]
+    fn parameter_expansions() {
+        assert_eq!(word("$HOME/x"), [param("HOME"), literal("/x")]);
+        assert_eq!(word("${a}b"), [param("a"), literal("b")]);
+        assert_eq!(word("$12"), [param("1"), literal("2")]);
+        assert_eq!(word("$?"), [param("?")]);
+        assert_eq!(word("a$"), [literal("a$")]);
+        let WordPart::Param(expansion) = &word("${v:-x y}")[0] else {
+            panic!("expected a parameter expansion");
+        };
+        assert!(matches!(
+            &expansion.op,
+            ParamOp::Default { colon: true, word } if word.unquoted() == "x y"
+        ));
+        let WordPart::Param(expansion) = &word("${v//a/b}")[0] else {
+            panic!("expected a parameter expansion");
+        };
+        assert!(matches!(
+            &expansion.op,
+            ParamOp::Replace {
+                mode: ReplaceMode::All,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn command_substitutions() {
+        assert!(matches!(
+            &word("$(echo a; echo b)")[..],
+            [WordPart::CommandSub(list)] if list.items.len() == 2
+        ));
+        assert!(matches!(&word("`echo a`")[..], [WordPart::CommandSub(_)]));
+        // A `)` inside quotes or a nested substitution doesn't end it
+        assert!(matches!(
+            &word("$(echo ')')")[..],
+            [WordPart::CommandSub(_)]
+        ));
+        assert!(matches!(
+            &word("$(echo $(ls))")[..],
+            [WordPart::CommandSub(_)]
+        ));
+        assert_incomplete("$(echo");
+        assert_incomplete("`echo");
+    }
+
+    #[test]
+    fn arithmetic_expansion() {
+        assert!(matches!(
+            &word("$((1 + (2 * 3)))")[..],
+            [WordPart::Arithmetic(expr)] if expr.unquoted() == "1 + (2 * 3)"
+        ));
+        assert_incomplete("$((1");
+    }
+
+    #[test]
+    fn here_document_operators() {
+        assert_eq!(ops("<< <<- <<<"), [Op::DLess, Op::DLessDash, Op::TLess]);
+    }
+
+    #[test]
+    fn duplication_operators() {
+        assert_eq!(
+            ops("<& >& <> &> &>>"),
+            [
+                Op::LessAnd,
+                Op::GreatAnd,
+                Op::LessGreat,
+                Op::AndGreat,
+                Op::AndDGreat
+            ]
+        );
+    }
+
+    #[test]
+    fn named_descriptors() {
+        assert_eq!(kinds("{fd}>x")[0], TokenKind::IoName("fd".to_string()));
+        // Anything else in braces is an ordinary word
+        assert!(matches!(kinds("{1x}>x")[0], TokenKind::Word(_)));
+        assert!(matches!(kinds("{fd} >x")[0], TokenKind::Word(_)));
+    }
+
+    #[test]
+    fn process_substitutions() {
+        assert!(matches!(
+            &word("<(ls)")[..],
+            [WordPart::ProcessSub { output: false, .. }]
+        ));
+        assert!(matches!(
+            &word(">(cat)")[..],
+            [WordPart::ProcessSub { output: true, .. }]
+        ));
+        // Separated from the parenthesis it is a redirection
+        assert_eq!(kinds("< (ls)")[0], TokenKind::Op(Op::Less));
+    }
+}
@@ -1,13 +1,10 @@
-use std::fs::OpenOptions;
-use std::io::{Read, Stdin};
-#[allow(unused_imports)]
-use std::io::{self, Write};
-
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
 use std::path::Path;
-use std::process::{self, Child, Command, Stdio};
-use std::str::FromStr;
-use std::{fs, os::unix::fs::PermissionsExt};
+use std::process;
 
+use nix::sys::signal::Signal;
 use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
@@ -15,47 +12,31 @@ use rustyline::hint::Hinter;
 
 use rustyline::validate::Validator;
 use rustyline::{Config, Editor, Helper};
-#[derive(Debug)]
-enum RedirectType {
-    Stdout,
-    Stderr,
-    Stdin,
-}
-
-#[derive(Debug)]
-struct Redirect {
-    redirect_type: RedirectType,
-    target: String,
-    append: bool,
-}
-#[derive(Debug, PartialEq)]
-
-enum ShellCommand {
-    Exit,
-    Echo,
-    Type,
-    Pwd,
-    Cd,
-    Unknown,
-}
-
-impl FromStr for ShellCommand {
-    type Err = ();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "exit" => Ok(ShellCommand::Exit),
-            "echo" => Ok(ShellCommand::Echo),
-            "type" => Ok(ShellCommand::Type),
-            "pwd" => Ok(ShellCommand::Pwd),
-            "cd" => Ok(ShellCommand::Cd),
-            _ => Ok(ShellCommand::Unknown),
-        }
-    }
-}
+mod arith;
+mod ast;
+mod brace;
+mod builtins;
+mod expand;
+mod glob;
+mod jobs;
+mod lexer;
+mod options;
+mod parser;
+mod pattern;
+mod redirect;
+mod shell;
+mod trap;
+mod vars;
+
+use options::SetOptions;
+use shell::{Flow, Shell};
+use trap::Condition;
 
 struct AutoCompiler {
     commands: Vec<String>,
+    /// Alias names, refreshed before each prompt.
+    aliases: Vec<String>,
 }
 
 impl Helper for AutoCompiler {}
@@ -82,6 +63,14 @@ impl Completer for AutoCompiler {
                 });
             }
         }
+        for alias in &self.aliases {
+            if alias.starts_with(prefix) {
+                matches.push(Pair {
+                    display: alias.clone(),
+                    replacement: format!("{} ", alias),
+                });
+            }
+        }
         let mut cmds = self.get_path_commands();
         cmds.sort();
         for cmd in cmds {
@@ -107,6 +96,7 @@ impl AutoCompiler {
                 "type ".into(),
                 "exit ".into(),
             ],
+            aliases: Vec::new(),
         }
     }
     fn get_path_commands(&self) -> Vec<String> {
@@ -131,163 +121,278 @@ impl AutoCompiler {
         cmds
     }
 }
-//
-fn parse(input: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let mut current = String::new();
-    let mut chars = input.chars().peekable();
-    let mut in_single = false;
-    let mut in_double = false;
+/// What the command line asked the shell to run.
+enum Mode {
+    Interactive,
+    /// `-c command`
+    Command(String),
+    /// A script file path
+    Script(String),
+    /// A script piped to stdin
+    Stdin,
+}
 
-    while let Some(ch) = chars.next() {
-        match ch {
-            '\'' if !in_double => {
-                in_single = !in_single;
+/// The parsed command line.
+struct Invocation {
+    mode: Mode,
+    /// Arguments after the command or script, which become `$1` and so on.
+    args: Vec<String>,
+    /// `-l`, or an `argv[0]` starting with `-` as `login` passes it.
+    login: bool,
+    /// Cleared by `--norc`, which skips `~/.rshrc` but not `$ENV`.
+    rc: bool,
+    /// Cleared by `--noprofile`.
+    profile: bool,
+    /// `set` options given as `-e`, `+x` or `-o pipefail`, by name.
+    options: Vec<(String, bool)>,
+}
+
+/// Parses `[option...] [-c command [name [arg...]]]` or
+/// `[option...] [script [arg...]]`. For `-c`, the first argument after the
+/// command becomes `$0`, as in other shells.
+fn parse_args(argv0: &str, args: &[String]) -> Result<Invocation, String> {
+    let mut command = false;
+    let mut invocation = Invocation {
+        mode: Mode::Interactive,
+        args: Vec::new(),
+        login: argv0.starts_with('-'),
+        rc: true,
+        profile: true,
+        options: Vec::new(),
+    };
+    let mut rest = args;
+    while let Some(arg) = rest.first() {
+        match arg.as_str() {
+            "--" => {
+                rest = &rest[1..];
+                break;
             }
-            '"' if !in_single => {
-                in_double = !in_double;
+            "-c" => command = true,
+            "-l" | "--login" => invocation.login = true,
+            "--norc" => invocation.rc = false,
+            "--noprofile" => invocation.profile = false,
+            "-o" | "+o" => {
+                let Some(name) = rest.get(1) else {
+                    return Err(format!("{}: option requires an argument", arg));
+                };
+                if SetOptions::default().get(name).is_none() {
+                    return Err(format!("{}: invalid option name", name));
+                }
+                invocation.options.push((name.clone(), arg == "-o"));
+                rest = &rest[1..];
             }
-            '\\' => {
-                if let Some(next) = chars.next() {
-                    if in_single {
-                        // Backslash is literal in single quotes
-                        current.push('\\');
-                        current.push(next);
-                    } else if in_double {
-                        // In double quotes, only " and \ are escaped
-                        if next == '"' || next == '\\' {
-                            current.push(next);
-                        } else {
-                            // keep backslash literal for other characters
-                            current.push('\\');
-                            current.push(next);
+            arg if arg.len() > 1 && (arg.starts_with('-') || arg.starts_with('+')) => {
+                let on = arg.starts_with('-');
+                for flag in arg[1..].chars() {
+                    match flag {
+                        'c' if on => {
+                            command = true;
+                            continue;
                         }
-                    } else {
-                        // Outside quotes, backslash escapes next char
-                        current.push(next);
+                        'l' if on => {
+                            invocation.login = true;
+                            continue;
+                        }
+                        _ => {}
                     }
+                    let Some(name) = SetOptions::flag_name(flag) else {
+                        return Err(format!("{}: invalid option", arg));
+                    };
+                    invocation.options.push((name.to_string(), on));
                 }
             }
-            ' ' if !in_single && !in_double => {
-                if !current.is_empty() {
-                    args.push(current);
-                    current = String::new();
-                }
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+
+    if command {
+        let Some((command, rest)) = rest.split_first() else {
+            return Err("-c: option requires an argument".to_string());
+        };
+        invocation.mode = Mode::Command(command.clone());
+        invocation.args = rest.to_vec();
+    } else if let Some((script, rest)) = rest.split_first() {
+        invocation.mode = Mode::Script(script.clone());
+        invocation.args = rest.to_vec();
+    } else if !io::stdin().is_terminal() {
+        invocation.mode = Mode::Stdin;
+    }
+    Ok(invocation)
+}
+
+/// Sources the startup files: the profile for login shells, then for
+/// interactive shells `~/.rshrc` and the file named by `$ENV`.
+fn load_startup_files(shell: &mut Shell, invocation: &Invocation) {
+    let home = shell.vars.get("HOME").map(str::to_string);
+    if invocation.login && invocation.profile {
+        source_if_exists(shell, "/etc/profile");
+        // The first of these that exists, like bash's ~/.bash_profile
+        if let Some(home) = &home {
+            let profile = [".rsh_profile", ".profile"]
+                .iter()
+                .map(|name| format!("{}/{}", home, name))
+                .find(|path| Path::new(path).is_file());
+            if let Some(profile) = profile {
+                source_if_exists(shell, &profile);
             }
-            _ => current.push(ch),
         }
     }
 
-    if !current.is_empty() {
-        args.push(current.trim().to_string());
+    let interactive = matches!(invocation.mode, Mode::Interactive);
+    if !interactive {
+        return;
     }
+    // A login shell has already had its configuration from the profile
+    if invocation.rc && !invocation.login {
+        if let Some(home) = &home {
+            source_if_exists(shell, &format!("{}/.rshrc", home));
+        }
+    }
+    if let Some(env) = shell.vars.get("ENV").map(str::to_string) {
+        // `$ENV` is itself expanded, so it can be set to e.g. `$HOME/.shrc`
+        match shell.expand_string(&env) {
+            Ok(path) if !path.is_empty() => source_if_exists(shell, &path),
+            Ok(_) => {}
+            Err(err) => eprintln!("{}: ENV: {}", shell.name, err),
+        }
+    }
+}
 
-    args
+fn source_if_exists(shell: &mut Shell, path: &str) {
+    if !Path::new(path).is_file() {
+        return;
+    }
+    if let Err(err) = shell.source_file(path) {
+        eprintln!(
+            "{}: {}: {}",
+            shell.name,
+            path,
+            shell::io_error_message(&err)
+        );
+    }
 }
 
-fn handle_args(args: Vec<String>) -> (Vec<String>, Vec<Redirect>) {
-    let mut final_args = Vec::new();
-    let mut redirects = Vec::new();
+fn main() {
+    let mut shell = Shell::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut invocation = match parse_args(&shell.name, &args) {
+        Ok(invocation) => invocation,
+        Err(err) => {
+            eprintln!("{}: {}", shell.name, err);
+            process::exit(2);
+        }
+    };
+    shell.interactive = matches!(invocation.mode, Mode::Interactive);
+    for (name, on) in &invocation.options {
+        shell.options.set(name, *on);
+    }
+    load_startup_files(&mut shell, &invocation);
 
-    let mut iter = args.into_iter().peekable();
-    while let Some(token) = iter.next() {
-        match token.as_str() {
-            ">" | "1>" | ">>" | "1>>" | "2>" | "2>>" => {
-                if let Some(path) = iter.next() {
-                    let redirect_type = if token.starts_with("2") {
-                        RedirectType::Stderr
+    let mut rest = std::mem::take(&mut invocation.args);
+    let status = match invocation.mode {
+        Mode::Interactive => {
+            run_interactive(&mut shell);
+            shell.last_status
+        }
+        Mode::Command(command) => {
+            if !rest.is_empty() {
+                shell.name = rest.remove(0);
+            }
+            shell.positional = rest;
+            let origin = format!("{}: -c", shell.name);
+            shell.run_script(&command, &origin)
+        }
+        Mode::Script(path) => {
+            let text = match fs::read(&path) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(err) => {
+                    let status = if err.kind() == io::ErrorKind::NotFound {
+                        127
                     } else {
-                        RedirectType::Stdout
+                        126
                     };
-                    let append = token.ends_with(">>");
-                    redirects.push(Redirect {
-                        redirect_type,
-                        target: path,
-                        append,
-                    });
-                } else {
-                    eprintln!("syntax error near unexpected token `newline`");
-                }
-            }
-            "<" => {
-                if let Some(path) = iter.next() {
-                    redirects.push(Redirect {
-                        redirect_type: RedirectType::Stdin,
-                        target: path,
-                        append: false,
-                    });
-                } else {
-                    eprintln!("syntax error near unexpected token `newline`");
+                    eprintln!(
+                        "{}: {}: {}",
+                        shell.name,
+                        path,
+                        shell::io_error_message(&err)
+                    );
+                    process::exit(status);
                 }
+            };
+            shell.name = path.clone();
+            shell.positional = rest;
+            shell.run_script(&text, &path)
+        }
+        Mode::Stdin => {
+            let mut text = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut text) {
+                eprintln!("{}: {}", shell.name, err);
+                process::exit(2);
             }
-            _ => final_args.push(token),
+            let origin = shell.name.clone();
+            shell.run_script(&text, &origin)
         }
-    }
-
-    (final_args, redirects)
+    };
+    shell.exit(status);
 }
 
-fn main() {
+/// Reads commands from the terminal through the line editor until end of
+/// input or `exit`.
+fn run_interactive(shell: &mut Shell) {
     let config = Config::builder()
         .completion_type(rustyline::CompletionType::List) // <- this is the key
         .build();
     let mut r1 = Editor::with_config(config).unwrap();
     r1.set_helper(Some(AutoCompiler::new()));
+    shell.enable_job_control();
     loop {
+        shell.run_pending_traps();
+        shell.report_jobs();
+        if let Some(helper) = r1.helper_mut() {
+            helper.aliases = shell.aliases.keys().cloned().collect();
+        }
         match r1.readline("$ ") {
             Ok(line) => {
-                let input = line.trim();
-                if !input.is_empty() {
-                    r1.add_history_entry(input).unwrap();
-                }
-
-                // print!("{}", input);
-
-                let mut args = parse(&input);
-
-                // println!("{:?}", args);
-                let mut pipe = false;
-                if input.contains(&"|".to_string()) {
-                    pipe = true;
+                let mut input = line.trim().to_string();
+                if input.is_empty() {
+                    continue;
                 }
-                let command = args.remove(0);
-                let new_command = &command;
 
-                // println!("{}", command);
-                let (new_args, redir) = handle_args(args.clone());
-                // println!("{:?}", new_args);
-                // println!("{:?}", re);
-                let n_command;
-                if pipe {
-                    n_command = ShellCommand::Unknown;
-                } else {
-                    n_command = command.parse::<ShellCommand>().unwrap();
-                }
-                match n_command {
-                    ShellCommand::Echo => {
-                        handle_echo(new_args, redir);
-                    }
-                    ShellCommand::Exit => {
-                        handle_exit();
+                // Keep reading while the command is unfinished, as after `if`
+                // or inside an open quote
+                let parsed = loop {
+                    match parser::parse(&input, &shell.aliases) {
+                        Err(err) if err.incomplete => match r1.readline("> ") {
+                            Ok(more) => {
+                                input.push('\n');
+                                input.push_str(&more);
+                            }
+                            Err(ReadlineError::Interrupted) => break None,
+                            Err(_) => break Some(Err(err)),
+                        },
+                        result => break Some(result),
                     }
-                    ShellCommand::Pwd => handle_pwd(),
-                    ShellCommand::Cd => handle_cd(new_args),
-                    ShellCommand::Type => handle_type(new_args),
-                    ShellCommand::Unknown => {
-                        // let command = args.remove(0);
-                        if pipe {
-                            run_pipe(input);
-                        } else {
-                            handle_unknown(new_command.to_string(), new_args, redir)
+                };
+                r1.add_history_entry(input.as_str()).unwrap();
+
+                match parsed {
+                    Some(Ok(list)) => {
+                        shell.run_list(&list);
+                        // An interrupted job abandons only the line it was on
+                        if shell.flow == Some(Flow::Interrupt) {
+                            shell.flow = None;
                         }
                     }
+                    Some(Err(err)) => {
+                        eprintln!("{}", err);
+                        shell.last_status = 2;
+                    }
+                    None => interrupt_prompt(shell),
                 }
             }
-            Err(ReadlineError::Interrupted) => {
-                // Handle Ctrl+C
-                println!("^C");
-                continue;
-            }
+            Err(ReadlineError::Interrupted) => interrupt_prompt(shell),
             Err(ReadlineError::Eof) => {
                 // Handle Ctrl+D
                 println!();
@@ -301,366 +406,67 @@ fn main() {
     }
 }
 
-fn open_file_for_redirect(target: &str, append: bool) -> std::fs::File {
-    let path = Path::new(target);
-
-    // Create parent directories if they don't exist
-    if let Some(parent) = path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent).unwrap_or_else(|e| {
-                eprintln!("cannot create directory {}: {}", parent.display(), e);
-                std::process::exit(1);
-            });
-        }
-    }
-
-    let file = if append {
-        OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)
-            .unwrap_or_else(|e| {
-                eprintln!("cannot open {}: {}", path.display(), e);
-                std::process::exit(1);
-            })
-    } else {
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)
-            .unwrap_or_else(|e| {
-                eprintln!("cannot open {}: {}", path.display(), e);
-                std::process::exit(1);
-            })
-    };
-
-    file
-}
-
-fn handle_echo(args: Vec<String>, redir: Vec<Redirect>) {
-    let s = args.join(" ");
-
-    // No redirection: print to terminal
-    if redir.is_empty() {
-        println!("{}", s);
-        return;
-    }
-
-    for r in redir {
-        match r.redirect_type {
-            RedirectType::Stdout => {
-                let mut file = open_file_for_redirect(&r.target, r.append);
-                // always add newline when echoing
-                writeln!(file, "{}", s).unwrap_or_else(|e| {
-                    eprintln!("echo: cannot write to {}: {}", r.target, e);
-                    std::process::exit(1);
-                });
-            }
-
-            RedirectType::Stderr => {
-                let mut file = open_file_for_redirect(&r.target, r.append);
-                write!(file, "{}", "").unwrap_or_else(|e| {
-                    eprintln!("echo: cannot write to {}: {}", r.target, e);
-                    std::process::exit(1);
-                });
-                println!("{}", s);
-            }
-
-            RedirectType::Stdin => {
-                // ignore stdin for echo
-            }
-        }
-    }
-}
-
-fn handle_unknown(command: String, args: Vec<String>, redir: Vec<Redirect>) {
-    let exec_path = find_exec_function(&command);
-
-    if exec_path.is_empty() {
-        eprintln!("{}: command not found", command);
-        return;
-    }
-
-    let mut cmd = std::process::Command::new(exec_path);
-    // println!("{:?}",cmd);
-    cmd.args(args);
-
-    for r in redir {
-        match r.redirect_type {
-            RedirectType::Stdout => {
-                if r.append {
-                    let mut file = OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(&r.target)
-                        .unwrap_or_else(|e| {
-                            eprintln!("{}: cannot append to {}: {}", command, r.target, e);
-                            std::process::exit(1);
-                        });
-
-                    // Ensure file ends with newline before appending command output
-                    if let Ok(content) = std::fs::read_to_string(&r.target) {
-                        if !content.is_empty() && !content.ends_with('\n') {
-                            writeln!(file).unwrap();
-                        }
-                    }
-
-                    cmd.stdout(file);
-                } else {
-                    let file = OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .truncate(true)
-                        .open(&r.target)
-                        .unwrap_or_else(|e| {
-                            eprintln!("{}: cannot write to {}: {}", command, r.target, e);
-                            std::process::exit(1);
-                        });
-                    cmd.stdout(file);
-                }
-            }
-
-            RedirectType::Stderr => {
-                let file = if r.append {
-                    OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(&r.target)
-                        .unwrap()
-                } else {
-                    OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .truncate(true)
-                        .open(&r.target)
-                        .unwrap()
-                };
-                cmd.stderr(file);
-            }
-            RedirectType::Stdin => {
-                let file = OpenOptions::new().read(true).open(&r.target).unwrap();
-                cmd.stdin(file);
-            }
-        }
-    }
-
-    match cmd.spawn() {
-        Ok(mut child) => {
-            child.wait().expect("Failed to wait on child process");
-        }
-        Err(e) => {
-            eprintln!("{}: failed to execute: {}", command, e);
-        }
-    }
+/// Handles Ctrl+C at a prompt, which abandons the line being typed.
+fn interrupt_prompt(shell: &mut Shell) {
+    println!("^C");
+    shell.last_status = 130;
+    // The line editor reads Ctrl+C as a key, so no SIGINT arrives to run
+    // the trap
+    shell.run_trap(Condition::Signal(Signal::SIGINT));
 }
 
-fn handle_exit() {
-    process::exit(0);
-}
-
-fn handle_pwd() {
-    match std::env::current_dir() {
-        Ok(path) => {
-            println!("{}", path.display().to_string())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The startup files a shell sources, each of which appends its name to
+    /// `$loaded`.
+    fn loaded(mode: Mode, login: bool, rc: bool, profile: bool, env: bool) -> String {
+        let home = env::temp_dir().join(format!("rsh-startup-{}", process::id()));
+        fs::create_dir_all(&home).unwrap();
+        for name in ["profile", "rc", "env"] {
+            let path = home.join(format!(".rsh_{}", name));
+            fs::write(path, format!("loaded=\"$loaded {}\"\n", name)).unwrap();
         }
-        Err(_) => {}
-    };
-}
-
-fn handle_cd(args: Vec<String>) {
-    let path = &args[0];
-
-    let mut path = path.to_string();
-
-    let home_dir = std::env::var("HOME").unwrap_or_default();
-    if path.starts_with("~") {
-        path = home_dir + &path[1..];
-    }
-
-    match std::env::set_current_dir(&path) {
-        Ok(_) => {}
-        Err(_) => {
-            println!("cd: {}: No such file or directory", path);
-        }
-    }
-}
+        fs::rename(home.join(".rsh_rc"), home.join(".rshrc")).unwrap();
 
-fn handle_type(args: Vec<String>) {
-    let path = &args[0];
-    let current_path = path.parse::<ShellCommand>().unwrap();
-    if current_path != ShellCommand::Unknown {
-        println!("{} is a shell builtin", path);
-    } else {
-        let result = handle_exec_function(path);
-        if !result.is_empty() {
-            println!("{} is {}", path, result);
+        let mut shell = Shell::new();
+        shell.vars.set("HOME", home.to_string_lossy().into_owned());
+        shell.vars.unset("loaded");
+        if env {
+            shell.vars.set("ENV", "$HOME/.rsh_env".to_string());
         } else {
-            println!("{} not found", path);
+            shell.vars.unset("ENV");
         }
+        let invocation = Invocation {
+            mode,
+            args: Vec::new(),
+            login,
+            rc,
+            profile,
+            options: Vec::new(),
+        };
+        load_startup_files(&mut shell, &invocation);
+        fs::remove_dir_all(&home).unwrap();
+        shell.vars.get("loaded").unwrap_or_default().to_string()
     }
-}
-
-
-fn handle_exec_function(path: &str) -> String {
-    let dir_paths = std::env::var("PATH").unwrap_or_default();
-    let dir_paths = dir_paths.split(':').collect::<Vec<&str>>();
-    let res = String::new();
-    for dir in dir_paths.iter() {
-        let full_path = format!("{}/{}", dir, path);
-        let new_path = std::path::Path::new(&full_path);
-
-        if new_path.exists() {
-            match fs::metadata(new_path) {
-                Ok(metadata) => {
-                    let permission = metadata.permissions();
-                    let mode = permission.mode();
-                    // Check if the file is executable by the user
-                    if mode & 0o111 != 0 {
-                        return new_path.display().to_string();
-                    };
-                }
-                Err(_) => {}
-            }
-        }
-    }
-
-    res
-}
 
-fn find_exec_function(path: &str) -> String {
-    let dir_paths = std::env::var("PATH").unwrap_or_default();
-    let dir_paths = dir_paths.split(':').collect::<Vec<&str>>();
-    let res = String::new();
-    for dir in dir_paths.iter() {
-        let full_path = format!("{}/{}", dir, path);
-        let new_path = std::path::Path::new(&full_path);
-
-        if new_path.exists() {
-            match fs::metadata(new_path) {
-                Ok(metadata) => {
-                    let permission = metadata.permissions();
-                    let mode = permission.mode();
-                    // Check if the file is executable by the user
-                    if mode & 0o111 != 0 {
-                        return path.to_string();
-                    };
-                }
-                Err(_) => {}
-            }
-        }
-    }
-
-    res
-}
-
-
-fn handle_builtin(command: ShellCommand, args: Vec<String>, writer: &mut impl Write) {
-    match command {
-        ShellCommand::Echo => handle_echo_cap(args, writer),
-        ShellCommand::Pwd => handle_pwd_cap(writer),
-        ShellCommand::Type => handle_type_with_writer(args, writer),
-        ShellCommand::Cd => handle_cd(args),
-        ShellCommand::Exit => handle_exit(),
-        ShellCommand::Unknown => ()
-    }
-}
-
-// Example built-in modifications
-fn handle_echo_cap(args: Vec<String>, writer: &mut impl Write) {
-    let text = args.join(" ") + "\n";
-    let _ = writer.write_all(text.as_bytes());
-}
-
-fn handle_pwd_cap(writer: &mut impl Write) {
-    if let Ok(path) = std::env::current_dir() {
-        let _ = writeln!(writer, "{}", path.display());
-    }
-}
-
-// Wrap your existing handle_type
-fn handle_type_with_writer(args: Vec<String>, writer: &mut impl Write) {
-    if args.is_empty() { return; }
-    let cmd = &args[0];
-    let parsed = cmd.parse::<ShellCommand>().unwrap_or(ShellCommand::Unknown);
-    if parsed != ShellCommand::Unknown {
-        let _ = writeln!(writer, "{} is a shell builtin", cmd);
-    } else {
-        let result = handle_exec_function(cmd); // your existing function
-        if !result.is_empty() {
-            let _ = writeln!(writer, "{} is {}", cmd, result);
-        } else {
-            let _ = writeln!(writer, "{} not found", cmd);
-        }
-    }
-}
-
-
-
-fn run_pipe(input: &str) {
-    let parts: Vec<&str> = input.split('|').map(str::trim).collect();
-    let mut previous_output: Option<Vec<u8>> = None;
-
-    for (i, cmd) in parts.iter().enumerate() {
-        let mut args = parse(cmd);
-        if args.is_empty() { continue; }
-
-        let program = args.remove(0);
-        let shell_cmd = program.parse::<ShellCommand>().unwrap_or(ShellCommand::Unknown);
-        let is_last = i == parts.len() - 1;
-
-        match shell_cmd {
-            // Built-ins that produce output
-            ShellCommand::Echo | ShellCommand::Pwd | ShellCommand::Type => {
-                let mut buffer = Vec::new();
-                handle_builtin(shell_cmd, args.clone(), &mut buffer);
-
-                if is_last {
-                    io::stdout().write_all(&buffer).unwrap();
-                } else {
-                    previous_output = Some(buffer);
-                }
-            }
-
-            // External commands or unknown
-            ShellCommand::Cd | ShellCommand::Exit | ShellCommand::Unknown => {
-                let mut command = Command::new(program);
-                command.args(&args);
-
-                // Feed previous output into stdin if present
-                if let Some(data) = previous_output.take() {
-                    let mut child = command
-                        .stdin(Stdio::piped())
-                        .stdout(if is_last { Stdio::inherit() } else { Stdio::piped() })
-                        .spawn()
-                        .unwrap();
-
-                    child.stdin.as_mut().unwrap().write_all(&data).unwrap();
-
-                    if !is_last {
-                        let mut buf = Vec::new();
-                        child.stdout.as_mut().unwrap().read_to_end(&mut buf).unwrap();
-                        previous_output = Some(buf);
-                    }
-
-                    child.wait().unwrap();
-                } else {
-                    let mut child = command
-                        .stdout(if is_last { Stdio::inherit() } else { Stdio::piped() })
-                        .spawn()
-                        .unwrap();
-
-                    if !is_last {
-                        let mut buf = Vec::new();
-                        child.stdout.as_mut().unwrap().read_to_end(&mut buf).unwrap();
-                        previous_output = Some(buf);
-                    }
-
-                    child.wait().unwrap();
-                }
-            }
-
-            ShellCommand::Exit => handle_exit(),
-        }
+    #[test]
+    fn startup_files() {
+        use Mode::{Command, Interactive};
+        let command = || Command("true".to_string());
+
+        // Interactive shells read ~/.rshrc, login shells the profile, and
+        // interactive ones of either kind `$ENV`
+        assert_eq!(loaded(Interactive, false, true, true, true), " rc env");
+        assert_eq!(loaded(Interactive, false, true, true, false), " rc");
+        assert_eq!(loaded(Interactive, true, true, true, true), " profile env");
+        assert_eq!(loaded(command(), true, true, true, true), " profile");
+        assert_eq!(loaded(command(), false, true, true, true), "");
+
+        // `--norc` and `--noprofile` skip only their own files
+        assert_eq!(loaded(Interactive, false, false, true, true), " env");
+        assert_eq!(loaded(Interactive, true, true, false, true), " env");
+        assert_eq!(loaded(command(), true, true, false, true), "");
     }
 }
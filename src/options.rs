@@ -0,0 +1,156 @@
+/// Options toggled with `shopt -s` / `shopt -u`.
+#[derive(Debug, Default, Clone)]
+pub struct Shopts {
+    /// Patterns that match nothing expand to nothing.
+    pub nullglob: bool,
+    /// Patterns that match nothing are an error.
+    pub failglob: bool,
+    /// `*` and `?` also match a leading `.`.
+    pub dotglob: bool,
+    /// `**` matches any number of directories.
+    pub globstar: bool,
+    /// Patterns match without regard to case.
+    pub nocaseglob: bool,
+}
+
+impl Shopts {
+    pub const NAMES: [&'static str; 5] =
+        ["dotglob", "failglob", "globstar", "nocaseglob", "nullglob"];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        Some(*self.field(name)?)
+    }
+
+    /// Sets an option, returning `false` if there is no such option.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match self.field_mut(name) {
+            Some(field) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&bool> {
+        match name {
+            "dotglob" => Some(&self.dotglob),
+            "failglob" => Some(&self.failglob),
+            "globstar" => Some(&self.globstar),
+            "nocaseglob" => Some(&self.nocaseglob),
+            "nullglob" => Some(&self.nullglob),
+            _ => None,
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+}
+
+/// Options toggled with `set -o` / `set +o`, most also by a letter as in
+/// `set -e`.
+#[derive(Debug, Default, Clone)]
+pub struct SetOptions {
+    /// Exit when a command fails, outside of conditions.
+    pub errexit: bool,
+    /// Patterns are not expanded.
+    pub noglob: bool,
+    /// Commands are read but not run.
+    pub noexec: bool,
+    /// Expanding an unset parameter is an error.
+    pub nounset: bool,
+    /// Print each command before running it.
+    pub xtrace: bool,
+    /// `>` refuses to overwrite an existing file.
+    pub noclobber: bool,
+    /// A pipeline fails if any stage fails.
+    pub pipefail: bool,
+}
+
+impl SetOptions {
+    pub const NAMES: [&'static str; 7] = [
+        "errexit",
+        "noclobber",
+        "noexec",
+        "noglob",
+        "nounset",
+        "pipefail",
+        "xtrace",
+    ];
+
+    /// Option letters, in the order `$-` lists them.
+    const FLAGS: [(char, &'static str); 6] = [
+        ('e', "errexit"),
+        ('f', "noglob"),
+        ('n', "noexec"),
+        ('u', "nounset"),
+        ('x', "xtrace"),
+        ('C', "noclobber"),
+    ];
+
+    /// The option a letter stands for.
+    pub fn flag_name(flag: char) -> Option<&'static str> {
+        Self::FLAGS
+            .iter()
+            .find(|(c, _)| *c == flag)
+            .map(|(_, name)| *name)
+    }
+
+    /// The letters of the options that are on, as `$-` shows them.
+    pub fn flags(&self) -> String {
+        Self::FLAGS
+            .iter()
+            .filter(|(_, name)| self.get(name) == Some(true))
+            .map(|(c, _)| *c)
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        Some(*self.field(name)?)
+    }
+
+    /// Sets an option, returning `false` if there is no such option.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match self.field_mut(name) {
+            Some(field) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&bool> {
+        match name {
+            "errexit" => Some(&self.errexit),
+            "noclobber" => Some(&self.noclobber),
+            "noexec" => Some(&self.noexec),
+            "noglob" => Some(&self.noglob),
+            "nounset" => Some(&self.nounset),
+            "pipefail" => Some(&self.pipefail),
+            "xtrace" => Some(&self.xtrace),
+            _ => None,
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "noclobber" => Some(&mut self.noclobber),
+            "noexec" => Some(&mut self.noexec),
+            "noglob" => Some(&mut self.noglob),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1077 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use crate::ast::{
+    AndOr, AndOrOp, CaseItem, CaseTerminator, Command, CompoundCommand, List, Pipeline, Redirect,
+    RedirectType, SimpleCommand, Span, Word, WordPart,
+};
+use crate::lexer::{Lexer, Op, Token, TokenKind};
+use crate::vars::is_valid_name;
+
+#[derive(Debug, Error)]
+#[error("{message}")]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    /// The input ended before the construct being parsed was complete.
+    pub incomplete: bool,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            incomplete: false,
+        }
+    }
+
+    pub fn incomplete(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            incomplete: true,
+        }
+    }
+
+    fn unexpected(token: &Token) -> Self {
+        if token.kind == TokenKind::Eof {
+            return Self::incomplete("syntax error: unexpected end of file", token.span);
+        }
+        Self::new(
+            format!("syntax error near unexpected token `{}'", token.describe()),
+            token.span,
+        )
+    }
+}
+
+/// Alias names and the text they stand for.
+pub type Aliases = BTreeMap<String, String>;
+
+/// Reserved words, which are never replaced by aliases.
+const RESERVED: [&str; 17] = [
+    "!", "{", "}", "if", "then", "elif", "else", "fi", "while", "until", "do", "done", "for", "in",
+    "case", "esac", "function",
+];
+
+/// Parses a complete line (or script) into a command list, expanding
+/// `aliases` in command position.
+pub fn parse(input: &str, aliases: &Aliases) -> Result<List, ParseError> {
+    parse_from_line(input, 1, aliases)
+}
+
+/// Like [`parse`], for input that starts on line `line` of a script, which
+/// is what its commands record as their lines.
+pub fn parse_from_line(input: &str, line: usize, aliases: &Aliases) -> Result<List, ParseError> {
+    let lexer = Lexer::new(input, aliases).starting_on_line(line);
+    let mut parser = Parser::new(lexer, aliases);
+    let list = parser.parse_list(&[])?;
+    let token = parser.next()?;
+    if token.kind != TokenKind::Eof {
+        return Err(ParseError::unexpected(&token));
+    }
+    Ok(list)
+}
+
+/// Parses the command list of a `$(...)` whose body starts where `lexer` is,
+/// returning it with the offset just past the closing `)`.
+pub fn parse_command_substitution<'a>(
+    lexer: Lexer<'a>,
+    aliases: &'a Aliases,
+) -> Result<(List, usize), ParseError> {
+    let mut parser = Parser::new(lexer, aliases);
+    let list = parser.parse_list(&[])?;
+    parser.expect_op(Op::RParen)?;
+    Ok((list, parser.lexer.pos()))
+}
+
+/// Where a token came from when it is the result of alias expansion.
+#[derive(Debug, Clone, Default)]
+struct AliasTag {
+    /// The aliases whose expansion produced this token, which must not be
+    /// expanded again within it.
+    chain: Vec<String>,
+    /// The token ends an alias value with a trailing blank, so the word
+    /// after it may be an alias too.
+    expand_next: bool,
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    aliases: &'a Aliases,
+    peeked: Option<(Token, AliasTag)>,
+    /// Tokens of expanded aliases, read before the lexer's.
+    queued: VecDeque<(Token, AliasTag)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(lexer: Lexer<'a>, aliases: &'a Aliases) -> Self {
+        Self {
+            lexer,
+            aliases,
+            peeked: None,
+            queued: VecDeque::new(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&Token, ParseError> {
+        Ok(&self.peek_tagged()?.0)
+    }
+
+    fn peek_tagged(&mut self) -> Result<&(Token, AliasTag), ParseError> {
+        if self.peeked.is_none() {
+            let next = match self.queued.pop_front() {
+                Some(next) => next,
+                None => (self.lexer.next_token()?, AliasTag::default()),
+            };
+            self.peeked = Some(next);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        Ok(self.next_tagged()?.0)
+    }
+
+    fn next_tagged(&mut self) -> Result<(Token, AliasTag), ParseError> {
+        self.peek_tagged()?;
+        Ok(self.peeked.take().unwrap())
+    }
+
+    /// Replaces the next word with the tokens of its alias, if it names one
+    /// that is not already being expanded. Returns whether it did.
+    fn expand_alias(&mut self) -> Result<bool, ParseError> {
+        let aliases = self.aliases;
+        let (token, tag) = self.peek_tagged()?;
+        let TokenKind::Word(word) = &token.kind else {
+            return Ok(false);
+        };
+        let Some(name) = word.as_literal() else {
+            return Ok(false);
+        };
+        if RESERVED.contains(&name) || tag.chain.iter().any(|active| active == name) {
+            return Ok(false);
+        }
+        let Some(value) = aliases.get(name) else {
+            return Ok(false);
+        };
+
+        let mut chain = tag.chain.clone();
+        chain.push(name.to_string());
+        let expand_next = tag.expand_next || value.ends_with([' ', '\t']);
+        let span = token.span;
+        let mut lexer = Lexer::new(value, aliases);
+        let mut tokens = Vec::new();
+        loop {
+            let mut token = lexer.next_token()?;
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            // Positions within the alias's value mean nothing in the input
+            token.span = span;
+            let tag = AliasTag {
+                chain: chain.clone(),
+                expand_next: false,
+            };
+            tokens.push((token, tag));
+        }
+        if let Some((_, tag)) = tokens.last_mut() {
+            tag.expand_next = expand_next;
+        }
+
+        self.peeked = None;
+        for token in tokens.into_iter().rev() {
+            self.queued.push_front(token);
+        }
+        Ok(true)
+    }
+
+    fn peek_op(&mut self) -> Result<Option<Op>, ParseError> {
+        Ok(match self.peek()?.kind {
+            TokenKind::Op(op) => Some(op),
+            _ => None,
+        })
+    }
+
+    /// Whether the next token is one of `words` used as a reserved word.
+    fn peek_reserved(&mut self, words: &[&str]) -> Result<bool, ParseError> {
+        Ok(match &self.peek()?.kind {
+            TokenKind::Word(word) => word.as_literal().is_some_and(|w| words.contains(&w)),
+            _ => false,
+        })
+    }
+
+    fn skip_newlines(&mut self) -> Result<(), ParseError> {
+        while self.peek()?.kind == TokenKind::Newline {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    fn expect_op(&mut self, op: Op) -> Result<Token, ParseError> {
+        let token = self.next()?;
+        if token.kind != TokenKind::Op(op) {
+            return Err(ParseError::unexpected(&token));
+        }
+        Ok(token)
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> Result<Token, ParseError> {
+        if !self.peek_reserved(&[word])? {
+            let token = self.next()?;
+            return Err(ParseError::unexpected(&token));
+        }
+        self.next()
+    }
+
+    /// Parses and-or lists until end of input, a `)` or `case` item
+    /// terminator, or one of the reserved
+    /// words in `terminators`.
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let mut list = List::default();
+        self.skip_newlines()?;
+        loop {
+            let at_end = matches!(
+                self.peek()?.kind,
+                TokenKind::Eof | TokenKind::Op(Op::RParen | Op::DSemi | Op::SemiAnd | Op::DSemiAnd)
+            );
+            if at_end || self.peek_reserved(terminators)? {
+                break;
+            }
+            let mut and_or = self.parse_and_or()?;
+            if self.peek_op()? == Some(Op::Amp) {
+                and_or.background = true;
+            }
+            list.items.push(and_or);
+            match self.peek()?.kind {
+                TokenKind::Op(Op::Semi | Op::Amp) | TokenKind::Newline => {
+                    self.next()?;
+                    self.skip_newlines()?;
+                }
+                _ => break,
+            }
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut span = first.span;
+        let mut rest = Vec::new();
+        loop {
+            let op = match self.peek_op()? {
+                Some(Op::AndIf) => AndOrOp::And,
+                Some(Op::OrIf) => AndOrOp::Or,
+                _ => break,
+            };
+            self.next()?;
+            self.skip_newlines()?;
+            let pipeline = self.parse_pipeline()?;
+            span = span.to(pipeline.span);
+            rest.push((op, pipeline));
+        }
+        Ok(AndOr {
+            first,
+            rest,
+            span,
+            background: false,
+        })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let bang = if self.peek_reserved(&["!"])? {
+            Some(self.next()?.span)
+        } else {
+            None
+        };
+        let first = self.parse_command()?;
+        let mut span = bang.map_or(first.span(), |bang| bang.to(first.span()));
+        let mut commands = vec![first];
+        while self.peek_op()? == Some(Op::Pipe) {
+            self.next()?;
+            self.skip_newlines()?;
+            let command = self.parse_command()?;
+            span = span.to(command.span());
+            commands.push(command);
+        }
+        Ok(Pipeline {
+            commands,
+            negated: bang.is_some(),
+            span,
+            text: self.lexer.text(span).to_string(),
+        })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        while self.expand_alias()? {}
+        if self.peek_op()? == Some(Op::LParen) {
+            let (open, tag) = self.next_tagged()?;
+            // `((` is only recognised in the input itself, not in alias values
+            if tag.chain.is_empty() {
+                if let Some(expr) = self.lexer.read_arithmetic_command()? {
+                    let span = Span::new(open.span.start, self.lexer.pos());
+                    return self.finish_compound(CompoundCommand::Arithmetic(expr), span);
+                }
+            }
+            let list = self.parse_body(&[])?;
+            let close = self.expect_op(Op::RParen)?;
+            return self.finish_compound(CompoundCommand::Subshell(list), open.span.to(close.span));
+        }
+        if self.peek_reserved(&["{"])? {
+            let open = self.next()?;
+            let list = self.parse_body(&["}"])?;
+            let close = self.expect_reserved("}")?;
+            return self
+                .finish_compound(CompoundCommand::BraceGroup(list), open.span.to(close.span));
+        }
+        if self.peek_reserved(&["if"])? {
+            return self.parse_if();
+        }
+        if self.peek_reserved(&["while", "until"])? {
+            return self.parse_while();
+        }
+        if self.peek_reserved(&["for"])? {
+            return self.parse_for();
+        }
+        if self.peek_reserved(&["case"])? {
+            return self.parse_case();
+        }
+        if self.peek_reserved(&["function"])? {
+            let open = self.next()?;
+            let token = self.next()?;
+            let name = match &token.kind {
+                TokenKind::Word(word) => word.as_literal().map(str::to_string),
+                _ => None,
+            };
+            let Some(name) = name else {
+                return Err(ParseError::unexpected(&token));
+            };
+            if self.peek_op()? == Some(Op::LParen) {
+                self.next()?;
+                self.expect_op(Op::RParen)?;
+            }
+            return self.finish_function(name, open.span);
+        }
+        // A closing reserved word cannot start a command
+        if self.peek_reserved(&["then", "elif", "else", "fi", "do", "done", "esac"])? {
+            let token = self.next()?;
+            return Err(ParseError::unexpected(&token));
+        }
+        self.parse_simple_command()
+    }
+
+    /// Parses the compound command that forms a function's body, once the
+    /// `name()` or `function name` part has been read.
+    fn finish_function(&mut self, name: String, start: Span) -> Result<Command, ParseError> {
+        self.skip_newlines()?;
+        let compound = self.peek_op()? == Some(Op::LParen)
+            || self.peek_reserved(&["{", "if", "while", "until", "for", "case"])?;
+        if !compound {
+            let token = self.next()?;
+            return Err(ParseError::unexpected(&token));
+        }
+        let body = self.parse_command()?;
+        let span = start.to(body.span());
+        Ok(Command::FunctionDef {
+            name,
+            body: Rc::new(body),
+            span,
+        })
+    }
+
+    /// Parses the non-empty list that makes up part of a compound command.
+    fn parse_body(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let list = self.parse_list(terminators)?;
+        if list.items.is_empty() {
+            let token = self.next()?;
+            return Err(ParseError::unexpected(&token));
+        }
+        Ok(list)
+    }
+
+    fn parse_if(&mut self) -> Result<Command, ParseError> {
+        let open = self.next()?;
+        let mut branches = Vec::new();
+        let mut else_body = None;
+        loop {
+            let condition = self.parse_body(&["then"])?;
+            self.expect_reserved("then")?;
+            let body = self.parse_body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            if self.peek_reserved(&["elif"])? {
+                self.next()?;
+                continue;
+            }
+            if self.peek_reserved(&["else"])? {
+                self.next()?;
+                else_body = Some(self.parse_body(&["fi"])?);
+            }
+            break;
+        }
+        let close = self.expect_reserved("fi")?;
+        self.finish_compound(
+            CompoundCommand::If {
+                branches,
+                else_body,
+            },
+            open.span.to(close.span),
+        )
+    }
+
+    fn parse_while(&mut self) -> Result<Command, ParseError> {
+        let until = self.peek_reserved(&["until"])?;
+        let open = self.next()?;
+        let condition = self.parse_body(&["do"])?;
+        let (body, close) = self.parse_do_group()?;
+        let command = if until {
+            CompoundCommand::Until { condition, body }
+        } else {
+            CompoundCommand::While { condition, body }
+        };
+        self.finish_compound(command, open.span.to(close.span))
+    }
+
+    fn parse_for(&mut self) -> Result<Command, ParseError> {
+        let open = self.next()?;
+        let token = self.next()?;
+        let name = match &token.kind {
+            TokenKind::Word(word) => match word.as_literal() {
+                Some(name) if is_valid_name(name) => name.to_string(),
+                _ => {
+                    return Err(ParseError::new(
+                        format!("`{}': not a valid identifier", word.unquoted()),
+                        token.span,
+                    ))
+                }
+            },
+            _ => return Err(ParseError::unexpected(&token)),
+        };
+
+        let mut words = None;
+        if self.peek_op()? == Some(Op::Semi) {
+            self.next()?;
+        } else {
+            self.skip_newlines()?;
+            if self.peek_reserved(&["in"])? {
+                self.next()?;
+                let mut list = Vec::new();
+                loop {
+                    let token = self.next()?;
+                    match token.kind {
+                        TokenKind::Word(word) => list.push(word),
+                        TokenKind::Op(Op::Semi) | TokenKind::Newline => break,
+                        _ => return Err(ParseError::unexpected(&token)),
+                    }
+                }
+                words = Some(list);
+            }
+        }
+        self.skip_newlines()?;
+
+        let (body, close) = self.parse_do_group()?;
+        self.finish_compound(
+            CompoundCommand::For { name, words, body },
+            open.span.to(close.span),
+        )
+    }
+
+    fn parse_case(&mut self) -> Result<Command, ParseError> {
+        let open = self.next()?;
+        let token = self.next()?;
+        let TokenKind::Word(word) = token.kind else {
+            return Err(ParseError::unexpected(&token));
+        };
+        self.skip_newlines()?;
+        self.expect_reserved("in")?;
+        self.skip_newlines()?;
+
+        let mut items = Vec::new();
+        while !self.peek_reserved(&["esac"])? {
+            if self.peek_op()? == Some(Op::LParen) {
+                self.next()?;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                let token = self.next()?;
+                let TokenKind::Word(pattern) = token.kind else {
+                    return Err(ParseError::unexpected(&token));
+                };
+                patterns.push(pattern);
+                if self.peek_op()? != Some(Op::Pipe) {
+                    break;
+                }
+                self.next()?;
+            }
+            self.expect_op(Op::RParen)?;
+
+            let body = self.parse_list(&["esac"])?;
+            let terminator = match self.peek_op()? {
+                Some(Op::DSemi) => CaseTerminator::Break,
+                Some(Op::SemiAnd) => CaseTerminator::FallThrough,
+                Some(Op::DSemiAnd) => CaseTerminator::Continue,
+                // The last item may leave out its terminator
+                _ => {
+                    items.push(CaseItem {
+                        patterns,
+                        body,
+                        terminator: CaseTerminator::Break,
+                    });
+                    break;
+                }
+            };
+            self.next()?;
+            self.skip_newlines()?;
+            items.push(CaseItem {
+                patterns,
+                body,
+                terminator,
+            });
+        }
+
+        let close = self.expect_reserved("esac")?;
+        self.finish_compound(
+            CompoundCommand::Case { word, items },
+            open.span.to(close.span),
+        )
+    }
+
+    /// Parses `do list done`, returning the list and the `done` token.
+    fn parse_do_group(&mut self) -> Result<(List, Token), ParseError> {
+        self.expect_reserved("do")?;
+        let body = self.parse_body(&["done"])?;
+        let close = self.expect_reserved("done")?;
+        Ok((body, close))
+    }
+
+    /// Collects the redirections that may follow a compound command.
+    fn finish_compound(
+        &mut self,
+        body: CompoundCommand,
+        mut span: Span,
+    ) -> Result<Command, ParseError> {
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.parse_redirect()? {
+            span = span.to(redirect.span);
+            redirects.push(redirect);
+        }
+        Ok(Command::Compound {
+            body,
+            redirects,
+            line: self.lexer.line_at(span.start),
+            span,
+        })
+    }
+
+    fn parse_simple_command(&mut self) -> Result<Command, ParseError> {
+        let mut assignments = Vec::new();
+        let mut words: Vec<Word> = Vec::new();
+        let mut redirects = Vec::new();
+        let mut span: Option<Span> = None;
+        // Whether the next word is in a position where aliases expand
+        let mut eligible = true;
+
+        loop {
+            if eligible {
+                while self.expand_alias()? {}
+            }
+            if let Some(redirect) = self.parse_redirect()? {
+                span = Some(span.map_or(redirect.span, |s| s.to(redirect.span)));
+                redirects.push(redirect);
+                continue;
+            }
+            if !matches!(self.peek()?.kind, TokenKind::Word(_)) {
+                break;
+            }
+            let (token, tag) = self.next_tagged()?;
+            let TokenKind::Word(word) = token.kind else {
+                unreachable!()
+            };
+            span = Some(span.map_or(word.span, |s| s.to(word.span)));
+            // `name()` on its own starts a function definition
+            let bare = assignments.is_empty() && words.is_empty() && redirects.is_empty();
+            if bare && self.peek_op()? == Some(Op::LParen) {
+                let Some(name) = word.as_literal() else {
+                    let token = self.next()?;
+                    return Err(ParseError::unexpected(&token));
+                };
+                self.next()?;
+                self.expect_op(Op::RParen)?;
+                return self.finish_function(name.to_string(), word.span);
+            }
+            // Assignments are only recognised before the command name
+            match word.as_assignment() {
+                Some(assignment) if words.is_empty() => assignments.push(assignment),
+                _ => {
+                    words.push(word);
+                    eligible = tag.expand_next;
+                }
+            }
+        }
+
+        match span {
+            Some(span) => Ok(Command::Simple(SimpleCommand {
+                assignments,
+                words,
+                redirects,
+                line: self.lexer.line_at(span.start),
+                span,
+            })),
+            None => {
+                let token = self.next()?;
+                Err(ParseError::unexpected(&token))
+            }
+        }
+    }
+
+    /// Parses a redirection such as `< word`, `2>> word` or `2>&1` if one
+    /// comes next.
+    fn parse_redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let start = self.peek()?.span;
+        let (fd, fd_var) = match &self.peek()?.kind {
+            TokenKind::IoNumber(fd) => {
+                let fd = *fd;
+                self.next()?;
+                (Some(fd), None)
+            }
+            TokenKind::IoName(name) => {
+                let name = name.clone();
+                self.next()?;
+                (None, Some(name))
+            }
+            TokenKind::Op(op) if is_redirect_op(*op) => (None, None),
+            _ => return Ok(None),
+        };
+
+        let op_token = self.next()?;
+        let op = match op_token.kind {
+            // `&>` always means both standard output and standard error
+            TokenKind::Op(Op::AndGreat | Op::AndDGreat) if fd.is_some() || fd_var.is_some() => {
+                return Err(ParseError::unexpected(&op_token))
+            }
+            TokenKind::Op(op) if is_redirect_op(op) => op,
+            _ => return Err(ParseError::unexpected(&op_token)),
+        };
+        let (redirect_type, append) = match op {
+            Op::Less => (RedirectType::Input, false),
+            Op::Great => (RedirectType::Output, false),
+            Op::DGreat => (RedirectType::Output, true),
+            Op::Clobber => (RedirectType::Output, false),
+            Op::LessGreat => (RedirectType::ReadWrite, false),
+            Op::AndGreat => (RedirectType::OutputAndError, false),
+            Op::AndDGreat => (RedirectType::OutputAndError, true),
+            Op::LessAnd => (RedirectType::DuplicateInput, false),
+            Op::GreatAnd => (RedirectType::DuplicateOutput, false),
+            _ => {
+                let mut redirect = self.parse_here(op, fd.unwrap_or(0), start)?;
+                redirect.fd_var = fd_var;
+                return Ok(Some(redirect));
+            }
+        };
+        // Redirections that read default to standard input
+        let fd = fd.unwrap_or(match op {
+            Op::Less | Op::LessGreat | Op::LessAnd => 0,
+            _ => 1,
+        });
+
+        let target = self.next()?;
+        let TokenKind::Word(target) = target.kind else {
+            return Err(ParseError::unexpected(&target));
+        };
+        Ok(Some(Redirect {
+            fd,
+            fd_var,
+            redirect_type,
+            span: start.to(target.span),
+            target,
+            append,
+            clobber: op == Op::Clobber,
+        }))
+    }
+
+    /// Parses the word after `<<`, `<<-` or `<<<`. A here-document's body is
+    /// read from the lines after the current one straight away.
+    fn parse_here(&mut self, op: Op, fd: u32, start: Span) -> Result<Redirect, ParseError> {
+        let target = self.next()?;
+        let TokenKind::Word(word) = target.kind else {
+            return Err(ParseError::unexpected(&target));
+        };
+        let span = start.to(word.span);
+        if op == Op::TLess {
+            return Ok(Redirect {
+                fd,
+                fd_var: None,
+                redirect_type: RedirectType::HereString,
+                target: word,
+                append: false,
+                clobber: false,
+                span,
+            });
+        }
+
+        // Quoting any part of the delimiter turns off expansion in the body
+        let quoted = !word
+            .parts
+            .iter()
+            .all(|part| matches!(part, WordPart::Literal(_)));
+        let delimiter = word.unquoted();
+        let body = self
+            .lexer
+            .read_heredoc(&delimiter, op == Op::DLessDash, start.start)?;
+        let part = if quoted {
+            WordPart::Quoted(body)
+        } else {
+            let parts = Lexer::new(&body, self.aliases)
+                .read_heredoc_body()
+                .map_err(|mut err| {
+                    // Positions inside the body don't map back onto the source
+                    err.span = span;
+                    err
+                })?;
+            WordPart::DoubleQuoted(parts)
+        };
+        Ok(Redirect {
+            fd,
+            fd_var: None,
+            redirect_type: RedirectType::HereDoc,
+            target: Word {
+                parts: vec![part],
+                span,
+            },
+            append: false,
+            clobber: false,
+            span,
+        })
+    }
+}
+
+fn is_redirect_op(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Less
+            | Op::Great
+            | Op::DGreat
+            | Op::Clobber
+            | Op::LessGreat
+            | Op::AndGreat
+            | Op::AndDGreat
+            | Op::LessAnd
+            | Op::GreatAnd
+            | Op::DLess
+            | Op::DLessDash
+            | Op::TLess
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{ParamOp, SimpleCommand};
+
+    fn parse_str(input: &str) -> List {
+        parse(input, &Aliases::new()).unwrap()
+    }
+
+    fn first_command(list: &List) -> &Command {
+        &list.items[0].first.commands[0]
+    }
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(simple) => simple,
+            other => panic!("expected a simple command, got {:?}", other),
+        }
+    }
+
+    /// The words of each simple command in the list, in order.
+    fn words(list: &List) -> Vec<Vec<String>> {
+        list.items
+            .iter()
+            .flat_map(|and_or| {
+                std::iter::once(&and_or.first).chain(and_or.rest.iter().map(|(_, p)| p))
+            })
+            .flat_map(|pipeline| &pipeline.commands)
+            .map(|command| simple(command).words.iter().map(Word::unquoted).collect())
+            .collect()
+    }
+
+    fn assert_incomplete(input: &str) {
+        let err = parse(input, &Aliases::new()).unwrap_err();
+        assert!(err.incomplete, "{:?} should be incomplete: {}", input, err);
+    }
+
+    #[test]
+    fn lists_and_pipelines() {
+        let list = parse_str("a | b && ! c || d; e &\nf");
+        assert_eq!(list.items.len(), 3);
+
+        let first = &list.items[0];
+        assert_eq!(first.first.commands.len(), 2);
+        assert!(!first.first.negated);
+        let ops: Vec<AndOrOp> = first.rest.iter().map(|(op, _)| *op).collect();
+        assert_eq!(ops, [AndOrOp::And, AndOrOp::Or]);
+        assert!(first.rest[0].1.negated);
+        assert_eq!(first.text(), "a | b && ! c || d");
+
+        assert!(list.items[1].background);
+        assert!(!list.items[2].background);
+        assert_eq!(words(&list), [["a"], ["b"], ["c"], ["d"], ["e"], ["f"]]);
+    }
+
+    #[test]
+    fn assignments_and_redirections() {
+        let list = parse_str("A=1 B=\"x y\" cmd arg >out 2>&1 C=2");
+        let command = simple(first_command(&list));
+        let names: Vec<&str> = command
+            .assignments
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["A", "B"]);
+        assert_eq!(command.assignments[1].value.unquoted(), "x y");
+        // An assignment after the command name is an ordinary argument
+        assert_eq!(words(&list), [["cmd", "arg", "C=2"]]);
+
+        let redirects = &command.redirects;
+        assert_eq!(redirects.len(), 2);
+        assert_eq!(
+            (redirects[0].fd, redirects[0].redirect_type),
+            (1, RedirectType::Output)
+        );
+        assert_eq!(
+            (redirects[1].fd, redirects[1].redirect_type),
+            (2, RedirectType::DuplicateOutput)
+        );
+        assert_eq!(redirects[1].target.unquoted(), "1");
+    }
+
+    #[test]
+    fn compound_commands() {
+        let list = parse_str("if a; then b; elif c; then d; else e; fi");
+        let Command::Compound {
+            body:
+                CompoundCommand::If {
+                    branches,
+                    else_body,
+                },
+            ..
+        } = first_command(&list)
+        else {
+            panic!("expected an if command");
+        };
+        assert_eq!(branches.len(), 2);
+        assert!(else_body.is_some());
+
+        let list = parse_str("for x in 1 2; do echo $x; done");
+        assert!(matches!(
+            first_command(&list),
+            Command::Compound { body: CompoundCommand::For { name, words: Some(words), .. }, .. }
+                if name == "x" && words.len() == 2
+        ));
+
+        let list = parse_str("(a; b) > out; { c; }");
+        assert!(matches!(
+            first_command(&list),
+            Command::Compound { body: CompoundCommand::Subshell(inner), redirects, .. }
+                if inner.items.len() == 2 && redirects.len() == 1
+        ));
+        assert!(matches!(
+            &list.items[1].first.commands[0],
+            Command::Compound {
+                body: CompoundCommand::BraceGroup(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn reserved_words_only_in_command_position() {
+        assert_eq!(
+            words(&parse_str("echo if then fi")),
+            [["echo", "if", "then", "fi"]]
+        );
+        assert_eq!(words(&parse_str("'if' x")), [["if", "x"]]);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for input in [
+            "if true; then",
+            "while a; do b",
+            "for x in a b",
+            "a |",
+            "a &&",
+            "{ echo",
+            "( echo",
+            "echo 'abc",
+            "echo \"abc",
+            "echo \\",
+        ] {
+            assert_incomplete(input);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for (input, token) in [
+            ("fi", "fi"),
+            (")", ")"),
+            ("a | | b", "|"),
+            ("; a", ";"),
+            ("if a; fi", "fi"),
+            ("a && ;", ";"),
+        ] {
+            let err = parse(input, &Aliases::new()).unwrap_err();
+            assert!(!err.incomplete, "{:?} should not be incomplete", input);
+            assert_eq!(
+                err.message,
+                format!("syntax error near unexpected token `{}'", token)
+            );
+        }
+    }
+
+    #[test]
+    fn case_items() {
+        let list = parse_str("case $x in a|b) one;; (c) two;& *) three;;& esac");
+        let Command::Compound {
+            body: CompoundCommand::Case { items, .. },
+            ..
+        } = first_command(&list)
+        else {
+            panic!("expected a case command");
+        };
+        let terminators: Vec<CaseTerminator> = items.iter().map(|item| item.terminator).collect();
+        assert_eq!(
+            terminators,
+            [
+                CaseTerminator::Break,
+                CaseTerminator::FallThrough,
+                CaseTerminator::Continue
+            ]
+        );
+        assert_eq!(items[0].patterns.len(), 2);
+
+        // The last item needs no terminator, and `esac` may follow a newline
+        let list = parse_str("case x in\n  x) echo\nesac");
+        assert!(matches!(
+            first_command(&list),
+            Command::Compound { body: CompoundCommand::Case { items, .. }, .. } if items.len() == 1
+        ));
+        assert_incomplete("case x in");
+        assert_incomplete("case x in a) echo;;");
+    }
+
+    #[test]
+    fn function_definitions() {
+        let list = parse_str("f() { echo hi; }; function g { :; }; function h() (echo)");
+        let names: Vec<&str> = list
+            .items
+            .iter()
+            .map(|and_or| match &and_or.first.commands[0] {
+                Command::FunctionDef { name, .. } => name.as_str(),
+                other => panic!("expected a function definition, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(names, ["f", "g", "h"]);
+        assert_incomplete("f()");
+        assert_incomplete("f() {");
+    }
+
+    #[test]
+    fn aliases() {
+        let aliases: Aliases = [
+            ("ll", "ls -l"),
+            ("ls", "ls -F"),
+            ("sudo", "sudo "),
+            ("both", "ll; echo"),
+            ("if", "echo"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let words_of = |input: &str| words(&parse(input, &aliases).unwrap());
+
+        // An alias may use another, but never itself
+        assert_eq!(words_of("ll x"), [["ls", "-F", "-l", "x"]]);
+        // Only words in command position are replaced, unless the
+        // previous alias ends in a blank
+        assert_eq!(words_of("echo ll"), [["echo", "ll"]]);
+        assert_eq!(words_of("sudo ll"), [["sudo", "ls", "-F", "-l"]]);
+        // Quoting a word keeps it from being an alias
+        assert_eq!(words_of("\\ll"), [["ll"]]);
+        // An alias may hold several commands
+        assert_eq!(
+            words_of("both hi"),
+            [vec!["ls", "-F", "-l"], vec!["echo", "hi"]]
+        );
+        // Reserved words are never aliased
+        let list = parse("if ll; then :; fi", &aliases).unwrap();
+        assert!(matches!(
+            &list.items[0].first.commands[0],
+            Command::Compound {
+                body: CompoundCommand::If { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn here_documents() {
+        let list = parse_str("cat <<EOF; echo next\nhello $name\nEOF\necho after\n");
+        assert_eq!(
+            words(&list),
+            [vec!["cat"], vec!["echo", "next"], vec!["echo", "after"]]
+        );
+        let redirect = &simple(&list.items[0].first.commands[0]).redirects[0];
+        assert_eq!(redirect.redirect_type, RedirectType::HereDoc);
+        let [WordPart::DoubleQuoted(parts)] = redirect.target.parts.as_slice() else {
+            panic!("expected an expanding body, got {:?}", redirect.target);
+        };
+        assert!(matches!(
+            &parts[1],
+            WordPart::Param(param) if param.name == "name" && param.op == ParamOp::Plain
+        ));
+
+        // A quoted delimiter keeps the body verbatim, and `<<-` strips tabs
+        let list = parse_str("cat <<'EOF'\n$x\nEOF\ncat <<-END\n\t\tindented\n\tEND\n");
+        let target = |i: usize| {
+            simple(&list.items[i].first.commands[0]).redirects[0]
+                .target
+                .clone()
+        };
+        assert_eq!(target(0).parts, [WordPart::Quoted("$x\n".to_string())]);
+        assert_eq!(target(1).unquoted(), "indented\n");
+
+        // Two here-documents on one line are read in order
+        let list = parse_str("cat <<A <<B\na\nA\nb\nB\n");
+        let redirects = &simple(&list.items[0].first.commands[0]).redirects;
+        assert_eq!(redirects[0].target.unquoted(), "a\n");
+        assert_eq!(redirects[1].target.unquoted(), "b\n");
+
+        assert_incomplete("cat <<EOF\nbody\n");
+        let list = parse_str("cat <<< 'a b'");
+        let redirect = &simple(first_command(&list)).redirects[0];
+        assert_eq!(redirect.redirect_type, RedirectType::HereString);
+        assert_eq!(redirect.target.unquoted(), "a b");
+    }
+
+    #[test]
+    fn command_lines() {
+        let list = parse_from_line("a\ncat <<E\nbody\nE\n\nb $(\nc)", 3, &Aliases::new()).unwrap();
+        assert_eq!(simple(first_command(&list)).line, 3);
+        assert_eq!(simple(&list.items[1].first.commands[0]).line, 4);
+        let b = simple(&list.items[2].first.commands[0]);
+        assert_eq!(b.line, 8);
+        let WordPart::CommandSub(inner) = &b.words[1].parts[0] else {
+            panic!("expected a command substitution");
+        };
+        assert_eq!(simple(first_command(inner)).line, 9);
+
+        let list = parse_str("\nif a\nthen b; fi");
+        assert!(matches!(
+            first_command(&list),
+            Command::Compound { line: 2, .. }
+        ));
+    }
+}
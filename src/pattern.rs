@@ -0,0 +1,364 @@
+/// A shell pattern, as used by `${v#pat}` and friends.
+///
+/// `*` matches any string, `?` any single character and `[...]` a bracket
+/// expression (with `!` or `^` negation, ranges and `[:class:]` names). A
+/// backslash makes the next character literal, which is how quoted parts of a
+/// word are kept from being treated as pattern characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    Bracket {
+        negated: bool,
+        items: Vec<BracketItem>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BracketItem {
+    Char(char),
+    Range(char, char),
+    Class(String),
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(Token::Char(chars[i + 1]));
+                    i += 2;
+                    continue;
+                }
+                '*' => {
+                    // Consecutive stars match the same as one
+                    if tokens.last() != Some(&Token::AnyString) {
+                        tokens.push(Token::AnyString);
+                    }
+                }
+                '?' => tokens.push(Token::AnyChar),
+                '[' => {
+                    if let Some((token, end)) = parse_bracket(&chars, i) {
+                        tokens.push(token);
+                        i = end;
+                        continue;
+                    }
+                    tokens.push(Token::Char('['));
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+        Self { tokens }
+    }
+
+    /// Whether the whole of `text` matches.
+    pub fn matches(&self, text: &str) -> bool {
+        self.matches_with(text, false)
+    }
+
+    /// Like `matches`, optionally ignoring case.
+    pub fn matches_with(&self, text: &str, ignore_case: bool) -> bool {
+        if ignore_case {
+            let tokens: Vec<Token> = self.tokens.iter().map(Token::to_lowercase).collect();
+            let chars: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+            return match_tokens(&tokens, &chars);
+        }
+        let chars: Vec<char> = text.chars().collect();
+        match_tokens(&self.tokens, &chars)
+    }
+
+    /// The text this pattern matches if it contains no pattern characters.
+    pub fn literal_text(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the pattern starts with a literal `.`, which is what lets a
+    /// glob match hidden files.
+    pub fn starts_with_dot(&self) -> bool {
+        self.tokens.first() == Some(&Token::Char('.'))
+    }
+
+    /// Byte length of the shortest or longest prefix of `text` that matches.
+    pub fn match_prefix(&self, text: &str, longest: bool) -> Option<usize> {
+        let mut ends: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        ends.push(text.len());
+        if longest {
+            ends.reverse();
+        }
+        ends.into_iter().find(|&end| self.matches(&text[..end]))
+    }
+
+    /// Byte offset of the shortest or longest suffix of `text` that matches.
+    pub fn match_suffix(&self, text: &str, longest: bool) -> Option<usize> {
+        let mut starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+        starts.push(text.len());
+        if !longest {
+            starts.reverse();
+        }
+        starts
+            .into_iter()
+            .find(|&start| self.matches(&text[start..]))
+    }
+}
+
+impl Token {
+    fn to_lowercase(&self) -> Token {
+        let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+        match self {
+            Token::Char(c) => Token::Char(lower(*c)),
+            Token::Bracket { negated, items } => Token::Bracket {
+                negated: *negated,
+                items: items
+                    .iter()
+                    .map(|item| match item {
+                        BracketItem::Char(c) => BracketItem::Char(lower(*c)),
+                        BracketItem::Range(lo, hi) => BracketItem::Range(lower(*lo), lower(*hi)),
+                        BracketItem::Class(class) => BracketItem::Class(class.clone()),
+                    })
+                    .collect(),
+            },
+            token => token.clone(),
+        }
+    }
+}
+
+/// Whether `text` contains an unescaped `*`, `?` or `[`.
+pub fn has_pattern_chars(text: &str) -> bool {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Escapes pattern characters so `text` only matches itself.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Parses a bracket expression starting at `chars[start] == '['`, returning
+/// the token and the index just past the closing `]`.
+fn parse_bracket(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Bracket { negated, items }, i + 1));
+        }
+        first = false;
+
+        if c == '[' && chars.get(i + 1) == Some(&':') {
+            let rest: String = chars[i + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                items.push(BracketItem::Class(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+
+        let c = if c == '\\' {
+            i += 1;
+            *chars.get(i)?
+        } else {
+            c
+        };
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&e| e != ']') {
+            let mut end = chars[i + 2];
+            let mut skip = 3;
+            if end == '\\' {
+                end = *chars.get(i + 3)?;
+                skip = 4;
+            }
+            items.push(BracketItem::Range(c, end));
+            i += skip;
+        } else {
+            items.push(BracketItem::Char(c));
+            i += 1;
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let (mut t, mut s) = (0, 0);
+    // Where to resume after the last `*` if a later token fails to match
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < text.len() {
+        if t < tokens.len() {
+            match &tokens[t] {
+                Token::AnyString => {
+                    backtrack = Some((t, s));
+                    t += 1;
+                    continue;
+                }
+                token if token_matches(token, text[s]) => {
+                    t += 1;
+                    s += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match backtrack {
+            Some((star, matched)) => {
+                t = star + 1;
+                s = matched + 1;
+                backtrack = Some((star, matched + 1));
+            }
+            None => return false,
+        }
+    }
+
+    tokens[t..].iter().all(|token| *token == Token::AnyString)
+}
+
+fn token_matches(token: &Token, c: char) -> bool {
+    match token {
+        Token::Char(p) => *p == c,
+        Token::AnyChar => true,
+        Token::AnyString => false,
+        Token::Bracket { negated, items } => {
+            items.iter().any(|item| bracket_item_matches(item, c)) != *negated
+        }
+    }
+}
+
+fn bracket_item_matches(item: &BracketItem, c: char) -> bool {
+    match item {
+        BracketItem::Char(p) => *p == c,
+        BracketItem::Range(lo, hi) => *lo <= c && c <= *hi,
+        BracketItem::Class(class) => match class.as_str() {
+            "alnum" => c.is_alphanumeric(),
+            "alpha" => c.is_alphabetic(),
+            "blank" => c == ' ' || c == '\t',
+            "cntrl" => c.is_control(),
+            "digit" => c.is_ascii_digit(),
+            "graph" => c.is_ascii_graphic(),
+            "lower" => c.is_lowercase(),
+            "print" => c.is_ascii_graphic() || c == ' ',
+            "punct" => c.is_ascii_punctuation(),
+            "space" => c.is_whitespace(),
+            "upper" => c.is_uppercase(),
+            "xdigit" => c.is_ascii_hexdigit(),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).matches(text)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(!matches("a*b*c", "aXXbYY"));
+        assert!(matches("?", "é"));
+        assert!(!matches("?", ""));
+        assert!(matches("??x", "abx"));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(matches("[abc]", "b"));
+        assert!(!matches("[abc]", "d"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(!matches("[!a-c]", "a"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[[:digit:]][[:alpha:]]", "1a"));
+        assert!(!matches("[[:upper:]]", "a"));
+        // An unclosed bracket is an ordinary character
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escapes_are_literal() {
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "x"));
+        assert!(matches(r"a\?", "a?"));
+        assert_eq!(escape("a*[b]?"), r"a\*\[b\]\?");
+        assert!(Pattern::new(&escape("a*[b]?")).matches("a*[b]?"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let pattern = Pattern::new("[a-c]*.TXT");
+        assert!(!pattern.matches("Bar.txt"));
+        assert!(pattern.matches_with("Bar.txt", true));
+    }
+
+    #[test]
+    fn prefix_and_suffix() {
+        let pattern = Pattern::new("*/");
+        assert_eq!(pattern.match_prefix("a/b/c", false), Some(2));
+        assert_eq!(pattern.match_prefix("a/b/c", true), Some(4));
+        assert_eq!(pattern.match_prefix("abc", true), None);
+
+        let pattern = Pattern::new(".*");
+        assert_eq!(pattern.match_suffix("f.tar.gz", false), Some(5));
+        assert_eq!(pattern.match_suffix("f.tar.gz", true), Some(1));
+
+        // An empty match counts
+        assert_eq!(Pattern::new("x*").match_prefix("abc", false), None);
+        assert_eq!(Pattern::new("*").match_prefix("abc", false), Some(0));
+    }
+
+    #[test]
+    fn pattern_characters() {
+        assert!(has_pattern_chars("a*"));
+        assert!(has_pattern_chars("[ab]"));
+        assert!(!has_pattern_chars("plain"));
+        assert!(!has_pattern_chars(r"a\*"));
+        assert_eq!(
+            Pattern::new(r"a\*b").literal_text(),
+            Some("a*b".to_string())
+        );
+        assert_eq!(Pattern::new("a*").literal_text(), None);
+        assert!(Pattern::new(".*").starts_with_dot());
+    }
+}
@@ -0,0 +1,319 @@
+//! Redirections, applied to the shell's own file descriptors so they work
+//! the same way for builtins, functions and compound commands as for
+//! external commands, which inherit them.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::shell::io_error_message;
+use crate::vars::Variables;
+
+/// Descriptors the shell opens for itself are moved to this number or
+/// above, out of the way of the low numbers scripts use.
+pub const SHELL_FD_BASE: RawFd = 10;
+
+/// An expanded redirection of one file descriptor.
+#[derive(Debug)]
+pub struct Redirect {
+    pub fd: Fd,
+    pub action: Action,
+}
+
+/// The descriptor a redirection applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fd {
+    Number(RawFd),
+    /// `{name}>file`: a free descriptor of 10 or above, whose number is
+    /// stored in the variable. It stays open after the command.
+    Allocate(String),
+}
+
+#[derive(Debug)]
+pub enum Action {
+    /// Open a file, for `<`, `>`, `>>` or `<>`.
+    Open { path: String, mode: OpenMode },
+    /// Make the descriptor a copy of another one, as for `2>&1`.
+    Duplicate(RawFd),
+    /// Close the descriptor, as for `2>&-`.
+    Close,
+    /// Read the text of a here-document or here-string.
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    Read,
+    Write,
+    Append,
+    ReadWrite,
+    /// Like `Write`, but refusing to overwrite an existing regular file, for
+    /// `>` with `set -o noclobber`.
+    Create,
+}
+
+impl OpenMode {
+    fn options(self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        match self {
+            OpenMode::Read => options.read(true),
+            OpenMode::Write => options.write(true).create(true).truncate(true),
+            OpenMode::Append => options.append(true).create(true),
+            OpenMode::ReadWrite => options.read(true).write(true).create(true),
+            OpenMode::Create => options.write(true).create_new(true),
+        };
+        options
+    }
+}
+
+/// The shell's own bookkeeping of descriptors: for each command whose
+/// redirections are in effect, the descriptors it replaced and saved copies
+/// of what they were, to put back when the command is done.
+#[derive(Debug, Default)]
+pub struct FdTable {
+    /// Innermost command last. Each entry is a redirected descriptor and
+    /// its saved copy, or `None` if it was closed before.
+    frames: Vec<Vec<(RawFd, Option<RawFd>)>>,
+}
+
+impl FdTable {
+    /// Applies redirections left to right, starting a frame that must be
+    /// ended with [`FdTable::restore`] or [`FdTable::keep`]. If one fails,
+    /// those already applied are undone and the error message is returned.
+    pub fn apply(&mut self, redirects: &[Redirect], vars: &mut Variables) -> Result<(), String> {
+        // Output already written belongs to wherever stdout pointed before
+        let _ = io::stdout().flush();
+        self.frames.push(Vec::new());
+        for redirect in redirects {
+            let result = match (&redirect.fd, &redirect.action) {
+                // Checked before saving anything, which could reuse a closed
+                // source's number; and saved copies are not the script's to use
+                (_, Action::Duplicate(source))
+                    if !is_open(*source) || self.is_saved_copy(*source) =>
+                {
+                    Err(format!("{}: Bad file descriptor", source))
+                }
+                (Fd::Number(fd), action) => {
+                    let fd = *fd;
+                    self.move_saved_copy(fd);
+                    let frame = self.frames.last_mut().unwrap();
+                    if frame.iter().all(|(redirected, _)| *redirected != fd) {
+                        frame.push((fd, save_fd(fd)));
+                    }
+                    apply_one(fd, action)
+                }
+                (Fd::Allocate(name), action) => allocate(action).map(|fd| {
+                    vars.set(name, fd.to_string());
+                }),
+            };
+            if let Err(err) = result {
+                self.restore();
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the innermost frame, putting back the descriptors it replaced.
+    pub fn restore(&mut self) {
+        let _ = io::stdout().flush();
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        for (fd, saved) in frame.into_iter().rev() {
+            // SAFETY: dup2 and close only operate on descriptor numbers.
+            unsafe {
+                match saved {
+                    Some(saved) => {
+                        libc::dup2(saved, fd);
+                        libc::close(saved);
+                    }
+                    None => {
+                        libc::close(fd);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the innermost frame, keeping its redirections for good, as for
+    /// `exec` without a command.
+    pub fn keep(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        for (_, saved) in frame {
+            if let Some(saved) = saved {
+                // SAFETY: close only operates on a descriptor number.
+                unsafe { libc::close(saved) };
+            }
+        }
+    }
+
+    fn is_saved_copy(&self, fd: RawFd) -> bool {
+        self.frames
+            .iter()
+            .flatten()
+            .any(|(_, saved)| *saved == Some(fd))
+    }
+
+    /// Moves a saved copy living on `fd` elsewhere, before a redirection
+    /// the script asked for replaces it.
+    fn move_saved_copy(&mut self, fd: RawFd) {
+        for frame in &mut self.frames {
+            for (_, saved) in frame.iter_mut() {
+                if *saved == Some(fd) {
+                    let moved = save_fd(fd);
+                    // SAFETY: close only operates on a descriptor number.
+                    unsafe { libc::close(fd) };
+                    *saved = moved;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn apply_one(fd: RawFd, action: &Action) -> Result<(), String> {
+    match action {
+        Action::Duplicate(source) => {
+            // SAFETY: dup2 only operates on descriptor numbers.
+            if *source != fd && unsafe { libc::dup2(*source, fd) } < 0 {
+                let err = io::Error::last_os_error();
+                return Err(format!("{}: {}", source, io_error_message(&err)));
+            }
+            Ok(())
+        }
+        Action::Close => {
+            // SAFETY: close only operates on a descriptor number; closing one
+            // that is not open is harmless.
+            unsafe { libc::close(fd) };
+            Ok(())
+        }
+        Action::Open { .. } | Action::Text(_) => install(open(action)?, fd),
+    }
+}
+
+/// Sets up the target of `action` on a new descriptor of 10 or above, for
+/// `{name}>file`, and returns its number.
+fn allocate(action: &Action) -> Result<RawFd, String> {
+    let file;
+    let source = match action {
+        Action::Duplicate(source) => *source,
+        Action::Close => unreachable!("`{{name}}>&-` names its descriptor when expanded"),
+        Action::Open { .. } | Action::Text(_) => {
+            file = open(action)?;
+            file.as_raw_fd()
+        }
+    };
+    // SAFETY: fcntl only operates on descriptor numbers.
+    let fd = unsafe { libc::fcntl(source, libc::F_DUPFD, SHELL_FD_BASE) };
+    if fd < 0 {
+        let err = io::Error::last_os_error();
+        return Err(format!("{}: {}", source, io_error_message(&err)));
+    }
+    Ok(fd)
+}
+
+/// Opens the file of an `Open` action, or one holding the text of a `Text`
+/// action.
+fn open(action: &Action) -> Result<File, String> {
+    match action {
+        Action::Open {
+            path,
+            mode: OpenMode::Create,
+        } => create(path),
+        Action::Open { path, mode } => mode
+            .options()
+            .open(path)
+            .map_err(|err| format!("{}: {}", path, io_error_message(&err))),
+        Action::Text(text) => here_document_file(text).map_err(|err| {
+            format!(
+                "cannot create temp file for here-document: {}",
+                io_error_message(&err)
+            )
+        }),
+        Action::Duplicate(_) | Action::Close => unreachable!("no file to open"),
+    }
+}
+
+/// Opens `path` for `OpenMode::Create`. The file is created exclusively, so
+/// one made by another process in the meantime is never truncated; anything
+/// already there may only be written to if it isn't a regular file, like
+/// /dev/null or a FIFO.
+fn create(path: &str) -> Result<File, String> {
+    let error = |err: io::Error| format!("{}: {}", path, io_error_message(&err));
+    match OpenMode::Create.options().open(path) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            // Checked on what was opened, which the path may no longer name
+            let file = OpenOptions::new().write(true).open(path).map_err(error)?;
+            match file.metadata() {
+                Ok(metadata) if !metadata.is_file() => Ok(file),
+                _ => Err(format!("{}: cannot overwrite existing file", path)),
+            }
+        }
+        result => result.map_err(error),
+    }
+}
+
+fn is_open(fd: RawFd) -> bool {
+    // SAFETY: fcntl only operates on descriptor numbers.
+    unsafe { libc::fcntl(fd, libc::F_GETFD) >= 0 }
+}
+
+/// Duplicates `fd` to a close-on-exec descriptor, so commands run while it
+/// is redirected don't inherit the copy. `None` if `fd` is not open.
+fn save_fd(fd: RawFd) -> Option<RawFd> {
+    // SAFETY: fcntl only operates on descriptor numbers.
+    let saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, SHELL_FD_BASE) };
+    (saved >= 0).then_some(saved)
+}
+
+/// Moves an open file onto descriptor `fd`.
+fn install(file: File, fd: RawFd) -> Result<(), String> {
+    let raw = file.into_raw_fd();
+    // SAFETY: fcntl, dup2 and close only operate on descriptor numbers, and
+    // `raw` is owned here.
+    unsafe {
+        if raw == fd {
+            // Already in place, but opened close-on-exec
+            libc::fcntl(fd, libc::F_SETFD, 0);
+            return Ok(());
+        }
+        let result = libc::dup2(raw, fd);
+        libc::close(raw);
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            return Err(format!("{}: {}", fd, io_error_message(&err)));
+        }
+    }
+    Ok(())
+}
+
+/// A file holding the text of a here-document or here-string, for a
+/// command to read as its input. The file is already unlinked, so the text
+/// can be any size and nothing is left behind.
+fn here_document_file(text: &str) -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("rsh-here-{}-{}", process::id(), n));
+        let mut file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+        fs::remove_file(&path)?;
+        file.write_all(text.as_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+        return Ok(file);
+    }
+}
@@ -0,0 +1,1364 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command};
+use std::rc::Rc;
+
+use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{self, fork, ForkResult, Pid};
+use os_pipe::PipeReader;
+
+use crate::ast::{
+    self, AndOr, AndOrOp, Assignment, CaseItem, CaseTerminator, CompoundCommand, List, Pipeline,
+    RedirectType, SimpleCommand, Word,
+};
+use crate::builtins::{self, ShellCommand};
+use crate::expand::ExpandError;
+use crate::jobs::{self, Job, JobTable, ProcessState};
+use crate::options::{SetOptions, Shopts};
+use crate::parser::{self, Aliases};
+use crate::pattern::Pattern;
+use crate::redirect::{Action, Fd, FdTable, OpenMode, Redirect, SHELL_FD_BASE};
+use crate::trap::{self, Condition, Traps};
+use crate::vars::{Variable, Variables};
+
+/// The prefix of `set -x` trace lines when `PS4` is unset.
+const DEFAULT_PS4: &str = "+ ";
+
+/// A `break` or `continue` unwinding through enclosing loops, where the
+/// count is how many loops it still applies to, or a `return`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Break(usize),
+    Continue(usize),
+    /// `return` unwinding to the function call.
+    Return,
+    /// A foreground job was interrupted from the keyboard, which abandons
+    /// the rest of the command line, as if the shell had got the SIGINT.
+    Interrupt,
+}
+
+/// What a function call saves so it can be undone when the call returns.
+#[derive(Debug, Default)]
+pub struct Frame {
+    /// The caller's positional parameters.
+    positional: Vec<String>,
+    /// Variables made local in this call, with the values they shadow.
+    pub locals: Vec<(String, Option<Variable>)>,
+}
+
+/// State that lives for the whole shell session.
+#[derive(Debug)]
+pub struct Shell {
+    /// Exit status of the most recently completed command, read by `$?`.
+    pub last_status: i32,
+    pub vars: Variables,
+    /// The shell's name, read by `$0`.
+    pub name: String,
+    /// The shell's process id, read by `$$`, which subshells keep.
+    pub pid: u32,
+    pub shopts: Shopts,
+    pub options: SetOptions,
+    /// Reading commands from a terminal, rather than a script or `-c`.
+    pub interactive: bool,
+    /// Where the script being run came from, such as its path, and the line
+    /// of it the current command is on, for error messages.
+    pub origin: String,
+    pub line_number: usize,
+    /// How many command substitutions deep the shell is. `set -x` repeats
+    /// the first character of `$PS4` once more for each.
+    pub subst_depth: usize,
+    /// `$1`, `$2` and so on.
+    pub positional: Vec<String>,
+    pub functions: HashMap<String, Rc<ast::Command>>,
+    pub aliases: Aliases,
+    /// Descriptors saved while redirections are in effect.
+    pub fds: FdTable,
+    /// One frame per function call in progress, innermost last.
+    pub frames: Vec<Frame>,
+    /// How many `source` commands are running, which lets `return` leave
+    /// a sourced file.
+    pub source_depth: usize,
+    /// Set by `break`, `continue` and `return` until the loop or function
+    /// call they target sees it.
+    pub flow: Option<Flow>,
+    /// How many loops are running, which limits `break` and `continue`.
+    pub loop_depth: usize,
+    /// Status of the last command substitution in the command being
+    /// expanded, which becomes the status of an assignment-only command.
+    subst_status: Option<i32>,
+    /// Processes started for `<(list)` and `>(list)`, oldest first.
+    process_subs: Vec<ProcessSub>,
+    /// Whether each pipeline gets a process group of its own and the
+    /// terminal while it runs in the foreground.
+    pub job_control: bool,
+    pub jobs: JobTable,
+    /// The process started by the last `&`, read by `$!`.
+    pub last_background: Option<Pid>,
+    /// The pipeline being run, to show if it is stopped.
+    job_text: String,
+    pub traps: Traps,
+    /// Set while a trap's action runs, which the `DEBUG` and `ERR` traps
+    /// don't fire for.
+    in_trap: bool,
+    /// How many commands are running as conditions, as of `if`, `while`
+    /// or `&&`, whose failure is expected and doesn't fire the `ERR` trap.
+    condition_depth: usize,
+}
+
+/// A process started for a process substitution.
+#[derive(Debug)]
+struct ProcessSub {
+    pid: Pid,
+    /// The shell's end of the pipe, open until the command given its path
+    /// is done.
+    fd: Option<RawFd>,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            last_status: 0,
+            vars: Variables::from_env(),
+            name: std::env::args().next().unwrap_or_default(),
+            pid: process::id(),
+            shopts: Shopts::default(),
+            options: SetOptions::default(),
+            interactive: false,
+            origin: String::new(),
+            line_number: 0,
+            subst_depth: 0,
+            positional: Vec::new(),
+            functions: HashMap::new(),
+            aliases: Aliases::new(),
+            fds: FdTable::default(),
+            frames: Vec::new(),
+            source_depth: 0,
+            flow: None,
+            loop_depth: 0,
+            subst_status: None,
+            process_subs: Vec::new(),
+            job_control: false,
+            jobs: JobTable::default(),
+            last_background: None,
+            job_text: String::new(),
+            traps: Traps::inherit(),
+            in_trap: false,
+            condition_depth: 0,
+        }
+    }
+
+    /// Turns on job control if the shell has a terminal.
+    pub fn enable_job_control(&mut self) {
+        self.job_control = jobs::enable();
+    }
+
+    /// Called in a newly forked child of the shell, which leaves process
+    /// groups to the shell. It can still list the shell's jobs, as for
+    /// `jobs | wc -l`, though they are not its children to wait for.
+    fn enter_child(&mut self) {
+        if self.job_control {
+            jobs::reset_signals();
+        }
+        self.job_control = false;
+        jobs::reset_sigpipe();
+        self.traps.enter_subshell();
+    }
+
+    /// Exits the shell with `status`, running the `EXIT` trap first.
+    pub fn exit(&mut self, status: i32) -> ! {
+        if let Some(action) = self.traps.take(Condition::Exit) {
+            self.last_status = status;
+            self.run_action(&action);
+        }
+        let _ = io::stdout().flush();
+        process::exit(status);
+    }
+
+    /// Runs the trap set on `condition`, if any, leaving `$?` as it was.
+    pub fn run_trap(&mut self, condition: Condition) {
+        if let Some(action) = self.traps.get(condition).map(str::to_string) {
+            let status = self.last_status;
+            self.run_action(&action);
+            self.last_status = status;
+        }
+    }
+
+    fn run_action(&mut self, action: &str) {
+        if action.is_empty() {
+            return;
+        }
+        let in_trap = std::mem::replace(&mut self.in_trap, true);
+        self.run_script(action, "trap");
+        self.in_trap = in_trap;
+    }
+
+    /// Runs the `DEBUG` or `ERR` trap. Like bash without `set -T`/`-E`,
+    /// functions don't inherit them, and they don't fire inside a trap.
+    fn run_event_trap(&mut self, condition: Condition) {
+        if !self.in_trap && self.frames.is_empty() {
+            self.run_trap(condition);
+        }
+    }
+
+    /// Runs the `RETURN` trap as a function or sourced file returns with
+    /// `status`. Inside a function it only runs if set during the call,
+    /// making it differ from `before`.
+    pub fn run_return_trap(&mut self, before: Option<&str>, status: i32) {
+        let action = self.traps.get(Condition::Return);
+        if action.is_none() || (!self.frames.is_empty() && action == before) {
+            return;
+        }
+        self.last_status = status;
+        self.run_trap(Condition::Return);
+    }
+
+    /// Runs the actions of signals caught since the last check, at a safe
+    /// point between commands.
+    pub fn run_pending_traps(&mut self) {
+        for signal in trap::take_pending() {
+            self.run_trap(Condition::Signal(signal));
+        }
+    }
+
+    /// Reports jobs that have finished or stopped since the last report,
+    /// as before a prompt, and forgets the finished ones.
+    pub fn report_jobs(&mut self) {
+        self.jobs.poll();
+        for id in self.jobs.ids() {
+            let job = self.jobs.get(id).unwrap();
+            if job.notified || !(job.is_done() || job.is_stopped()) {
+                continue;
+            }
+            eprintln!("{}", self.jobs.format(job, false));
+            if job.is_done() {
+                self.jobs.remove(id);
+            } else if let Some(job) = self.jobs.get_mut(id) {
+                job.notified = true;
+            }
+        }
+    }
+
+    /// Reports a foreground job killed by a signal. Interrupting one from
+    /// the keyboard, which echoed `^C`, just ends the line, and a broken
+    /// pipe is normal for a pipeline stage.
+    fn report_death(&mut self, signal: Signal, core: bool) {
+        match signal {
+            Signal::SIGPIPE => {}
+            Signal::SIGINT => {
+                if self.job_control {
+                    eprintln!();
+                    self.flow = Some(Flow::Interrupt);
+                }
+            }
+            _ => eprintln!("{}", jobs::death_message(signal, core)),
+        }
+    }
+
+    /// Waits for a job running in the foreground, giving it the terminal
+    /// meanwhile. If it stops, it goes in the job table.
+    pub fn wait_foreground(&mut self, mut job: Job) -> i32 {
+        if self.job_control {
+            jobs::give_terminal(job.pgid);
+        }
+        job.wait();
+        if self.job_control {
+            jobs::give_terminal(unistd::getpgrp());
+        }
+        if !job.is_stopped() {
+            if let Some(ProcessState::Signaled(signal, core)) =
+                job.processes.last().map(|p| p.state)
+            {
+                self.report_death(signal, core);
+            }
+            return if self.options.pipefail {
+                job.failed_status()
+            } else {
+                job.status()
+            };
+        }
+        let status = job.status();
+        let id = self.jobs.insert(job);
+        let job = self.jobs.get(id).unwrap();
+        eprintln!("\n{}", self.jobs.format(job, false));
+        status
+    }
+
+    /// Runs script text one complete command at a time, so each command
+    /// sees the effects of the ones before it. A syntax error stops the
+    /// script with status 2, as does a `break`, `continue` or `return` that
+    /// leaves it. Errors are reported against `origin`, such as the
+    /// script's path.
+    pub fn run_script(&mut self, text: &str, origin: &str) -> i32 {
+        let outer = (
+            std::mem::replace(&mut self.origin, origin.to_string()),
+            self.line_number,
+        );
+        let status = self.run_script_lines(text);
+        (self.origin, self.line_number) = outer;
+        status
+    }
+
+    fn run_script_lines(&mut self, text: &str) -> i32 {
+        let mut input = String::new();
+        let mut line_number = 0;
+        let mut status = 0;
+        for line in text.split_inclusive('\n') {
+            line_number += 1;
+            let first_line = line_number - input.matches('\n').count();
+            input.push_str(line);
+            match parser::parse_from_line(&input, first_line, &self.aliases) {
+                Ok(list) => {
+                    input.clear();
+                    status = self.run_list(&list);
+                    if self.flow.is_some() {
+                        return status;
+                    }
+                }
+                Err(err) if err.incomplete => continue,
+                Err(err) => {
+                    eprintln!("{}: line {}: {}", self.origin, line_number, err);
+                    self.last_status = 2;
+                    return 2;
+                }
+            }
+        }
+        // Whatever is left is a command the script never finished
+        if let Err(err) = parser::parse(&input, &self.aliases) {
+            eprintln!("{}: line {}: {}", self.origin, line_number, err);
+            self.last_status = 2;
+            return 2;
+        }
+        status
+    }
+
+    /// Reads and runs the commands in a file in the current shell.
+    pub fn source_file(&mut self, path: &str) -> io::Result<i32> {
+        let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        Ok(self.run_script(&text, path))
+    }
+
+    pub fn run_list(&mut self, list: &List) -> i32 {
+        for and_or in &list.items {
+            // `set -n` checks a script's syntax without running any more of it
+            if self.flow.is_some() || (self.options.noexec && !self.interactive) {
+                break;
+            }
+            self.last_status = if and_or.background {
+                self.run_background(and_or)
+            } else {
+                self.run_and_or(and_or)
+            };
+            self.run_pending_traps();
+        }
+        self.last_status
+    }
+
+    /// Starts an and-or list in the background as a new job.
+    fn run_background(&mut self, and_or: &AndOr) -> i32 {
+        let _ = io::stdout().flush();
+        // SAFETY: the shell is single-threaded, so the child may keep running Rust code.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                let job_control = self.job_control;
+                if job_control {
+                    jobs::setup_child(0, false);
+                } else if let Ok(null) = fs::File::open("/dev/null") {
+                    // Without job control it must not compete for the terminal
+                    move_fd(null.as_raw_fd(), 0);
+                }
+                self.enter_child();
+                if !job_control {
+                    self.traps.ignore_interrupts();
+                }
+                let status = match (&and_or.first.commands[..], and_or.rest.is_empty()) {
+                    ([command], true) if !and_or.first.negated => self.run_stage(command),
+                    _ => self.run_and_or(and_or),
+                };
+                self.exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                if self.job_control {
+                    let _ = unistd::setpgid(child, child);
+                }
+                let id = self
+                    .jobs
+                    .insert(Job::new(child, vec![child], and_or.text()));
+                self.last_background = Some(child);
+                if self.job_control {
+                    eprintln!("[{}] {}", id, child);
+                }
+                0
+            }
+            Err(e) => {
+                eprintln!("fork: {}", e);
+                1
+            }
+        }
+    }
+
+    fn run_and_or(&mut self, and_or: &AndOr) -> i32 {
+        // All but the last pipeline are tested by the `&&` or `||` after them
+        let last = and_or.rest.len();
+        let mut status = self.run_checked(&and_or.first, last > 0);
+        for (i, (op, pipeline)) in and_or.rest.iter().enumerate() {
+            if self.flow.is_some() {
+                break;
+            }
+            let run_next = match op {
+                AndOrOp::And => status == 0,
+                AndOrOp::Or => status != 0,
+            };
+            if run_next {
+                self.last_status = status;
+                status = self.run_checked(pipeline, i + 1 < last);
+            }
+        }
+        status
+    }
+
+    /// Runs a pipeline, firing the `ERR` trap and then exiting under
+    /// `set -e` if it fails where failure is not expected: it is not
+    /// `tested`, negated or part of a condition. Compound commands have
+    /// already done so for the commands in them.
+    fn run_checked(&mut self, pipeline: &Pipeline, tested: bool) -> i32 {
+        if tested || pipeline.negated {
+            return self.run_condition(|shell| shell.run_pipeline(pipeline));
+        }
+        let status = self.run_pipeline(pipeline);
+        let checked_inside = matches!(
+            &pipeline.commands[..],
+            [ast::Command::Compound { body, .. }] if !matches!(body, CompoundCommand::Subshell(_))
+        );
+        if status != 0 && self.condition_depth == 0 && self.flow.is_none() && !checked_inside {
+            self.last_status = status;
+            self.run_event_trap(Condition::Err);
+            if self.options.errexit {
+                self.exit(status);
+            }
+        }
+        status
+    }
+
+    /// Runs `f` as a condition, whose failure is not an error.
+    fn run_condition(&mut self, f: impl FnOnce(&mut Shell) -> i32) -> i32 {
+        self.condition_depth += 1;
+        let status = f(self);
+        self.condition_depth -= 1;
+        status
+    }
+
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> i32 {
+        self.job_text.clone_from(&pipeline.text);
+        let status = if pipeline.commands.len() > 1 {
+            self.run_pipe(pipeline)
+        } else {
+            self.run_command(&pipeline.commands[0])
+        };
+        if pipeline.negated {
+            (status == 0) as i32
+        } else {
+            status
+        }
+    }
+
+    fn run_command(&mut self, command: &ast::Command) -> i32 {
+        let mark = self.process_subs.len();
+        let status = match command {
+            ast::Command::Simple(simple) => self.run_simple(simple, false),
+            ast::Command::FunctionDef { name, body, .. } => {
+                self.functions.insert(name.clone(), Rc::clone(body));
+                0
+            }
+            ast::Command::Compound {
+                body,
+                redirects,
+                line,
+                ..
+            } => {
+                self.line_number = *line;
+                match self.expand_redirects(redirects) {
+                    Ok(redirects) => {
+                        self.with_redirects(&redirects, |shell| shell.run_compound(body))
+                    }
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        1
+                    }
+                }
+            }
+        };
+        self.finish_process_subs(mark);
+        status
+    }
+
+    fn run_compound(&mut self, body: &CompoundCommand) -> i32 {
+        match body {
+            CompoundCommand::BraceGroup(list) => self.run_list(list),
+            CompoundCommand::Subshell(list) => self.run_forked(|shell| shell.run_list(list)),
+            CompoundCommand::Arithmetic(expr) => match self.arithmetic_command(expr) {
+                Ok(value) => i32::from(value == 0),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    1
+                }
+            },
+            CompoundCommand::If {
+                branches,
+                else_body,
+            } => self.run_if(branches, else_body.as_ref()),
+            CompoundCommand::While { condition, body } => self.run_while(condition, body, false),
+            CompoundCommand::Until { condition, body } => self.run_while(condition, body, true),
+            CompoundCommand::Case { word, items } => self.run_case(word, items),
+            CompoundCommand::For { name, words, body } => {
+                self.run_for(name, words.as_deref(), body)
+            }
+        }
+    }
+
+    fn run_if(&mut self, branches: &[(List, List)], else_body: Option<&List>) -> i32 {
+        for (condition, body) in branches {
+            let status = self.run_condition(|shell| shell.run_list(condition));
+            if self.flow.is_some() {
+                return status;
+            }
+            if status == 0 {
+                return self.run_list(body);
+            }
+        }
+        match else_body {
+            Some(body) => self.run_list(body),
+            None => 0,
+        }
+    }
+
+    /// Runs a `while` loop, or an `until` loop when `until` is set. The
+    /// status is that of the last body run, or 0 if it never ran.
+    fn run_while(&mut self, condition: &List, body: &List, until: bool) -> i32 {
+        let mut status = 0;
+        self.loop_depth += 1;
+        loop {
+            let result = self.run_condition(|shell| shell.run_list(condition));
+            if !self.continue_loop() {
+                break;
+            }
+            if (result == 0) == until {
+                break;
+            }
+            status = self.run_list(body);
+            if !self.continue_loop() {
+                break;
+            }
+        }
+        self.loop_depth -= 1;
+        status
+    }
+
+    fn run_for(&mut self, name: &str, words: Option<&[Word]>, body: &List) -> i32 {
+        let values = match words {
+            Some(words) => match self.expand_words(words) {
+                Ok(values) => values,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return 1;
+                }
+            },
+            None => self.positional.clone(),
+        };
+
+        let mut status = 0;
+        self.loop_depth += 1;
+        for value in values {
+            self.vars.set(name, value);
+            status = self.run_list(body);
+            if !self.continue_loop() {
+                break;
+            }
+        }
+        self.loop_depth -= 1;
+        status
+    }
+
+    /// Runs the body of the first `case` item with a pattern matching
+    /// `word`, continuing as that item's terminator says. The status is that
+    /// of the last body run, or 0 if nothing matched.
+    fn run_case(&mut self, word: &Word, items: &[CaseItem]) -> i32 {
+        let subject = match self.expand_word(word) {
+            Ok(subject) => subject,
+            Err(err) => {
+                eprintln!("{}", err);
+                return 1;
+            }
+        };
+
+        let mut status = 0;
+        let mut fall_through = false;
+        for item in items {
+            if !fall_through {
+                match self.case_matches(&subject, &item.patterns) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return 1;
+                    }
+                }
+            }
+            status = self.run_list(&item.body);
+            match item.terminator {
+                CaseTerminator::Break => return status,
+                CaseTerminator::FallThrough => fall_through = true,
+                CaseTerminator::Continue => fall_through = false,
+            }
+            if self.flow.is_some() {
+                break;
+            }
+        }
+        status
+    }
+
+    fn case_matches(&mut self, subject: &str, patterns: &[Word]) -> Result<bool, ExpandError> {
+        for pattern in patterns {
+            if Pattern::new(&self.expand_pattern(pattern)?).matches(subject) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Consumes a pending `break` or `continue` aimed at the innermost
+    /// loop, returning whether that loop should go on.
+    fn continue_loop(&mut self) -> bool {
+        match self.flow.take() {
+            None => true,
+            Some(flow @ (Flow::Return | Flow::Interrupt)) => {
+                self.flow = Some(flow);
+                false
+            }
+            Some(Flow::Break(n)) => {
+                if n > 1 {
+                    self.flow = Some(Flow::Break(n - 1));
+                }
+                false
+            }
+            Some(Flow::Continue(n)) => {
+                if n > 1 {
+                    self.flow = Some(Flow::Continue(n - 1));
+                    return false;
+                }
+                true
+            }
+        }
+    }
+
+    /// Runs `f` in a forked copy of the shell, as a foreground job, and
+    /// waits for its status.
+    fn run_forked(&mut self, f: impl FnOnce(&mut Shell) -> i32) -> i32 {
+        // Anything still buffered would otherwise be written by both processes
+        let _ = io::stdout().flush();
+        // SAFETY: the shell is single-threaded, so the child may keep running Rust code.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                if self.job_control {
+                    jobs::setup_child(0, true);
+                }
+                self.enter_child();
+                let status = f(self);
+                self.exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                if self.job_control {
+                    let _ = unistd::setpgid(child, child);
+                }
+                let text = self.job_text.clone();
+                self.wait_foreground(Job::new(child, vec![child], text))
+            }
+            Err(e) => {
+                eprintln!("fork: {}", e);
+                1
+            }
+        }
+    }
+
+    fn expand_redirects(
+        &mut self,
+        redirects: &[ast::Redirect],
+    ) -> Result<Vec<Redirect>, ExpandError> {
+        let mut expanded = Vec::new();
+        for r in redirects {
+            let target = self.expand_word(&r.target)?;
+            let output_mode = if r.append {
+                OpenMode::Append
+            } else if self.options.noclobber && !r.clobber {
+                OpenMode::Create
+            } else {
+                OpenMode::Write
+            };
+            let action = match r.redirect_type {
+                RedirectType::Input => Action::Open {
+                    path: target,
+                    mode: OpenMode::Read,
+                },
+                RedirectType::Output => Action::Open {
+                    path: target,
+                    mode: output_mode,
+                },
+                RedirectType::ReadWrite => Action::Open {
+                    path: target,
+                    mode: OpenMode::ReadWrite,
+                },
+                RedirectType::HereDoc => Action::Text(target),
+                RedirectType::HereString => Action::Text(target + "\n"),
+                RedirectType::OutputAndError => {
+                    push_output_and_error(&mut expanded, target, output_mode);
+                    continue;
+                }
+                RedirectType::DuplicateInput | RedirectType::DuplicateOutput => {
+                    match target.as_str() {
+                        "-" => Action::Close,
+                        n if n.bytes().all(|b| b.is_ascii_digit()) && !n.is_empty() => {
+                            match n.parse() {
+                                Ok(source) => Action::Duplicate(source),
+                                Err(_) => {
+                                    return Err(ExpandError(format!("{}: Bad file descriptor", n)))
+                                }
+                            }
+                        }
+                        // `>& file` is another way to write `&> file`
+                        _ if r.redirect_type == RedirectType::DuplicateOutput
+                            && r.fd == 1
+                            && r.fd_var.is_none() =>
+                        {
+                            push_output_and_error(&mut expanded, target, output_mode);
+                            continue;
+                        }
+                        _ => return Err(ExpandError(format!("{}: ambiguous redirect", target))),
+                    }
+                }
+            };
+            let fd = match &r.fd_var {
+                // `{name}>&-` closes the descriptor the variable holds
+                Some(name) if matches!(action, Action::Close) => {
+                    match self.vars.get(name).and_then(|value| value.parse().ok()) {
+                        Some(fd) => Fd::Number(fd),
+                        None => return Err(ExpandError(format!("{}: ambiguous redirect", name))),
+                    }
+                }
+                Some(name) => Fd::Allocate(name.clone()),
+                None => Fd::Number(r.fd as RawFd),
+            };
+            expanded.push(Redirect { fd, action });
+        }
+        Ok(expanded)
+    }
+
+    fn expand_assignments(
+        &mut self,
+        assignments: &[Assignment],
+    ) -> Result<Vec<(String, String)>, ExpandError> {
+        assignments
+            .iter()
+            .map(|a| Ok((a.name.clone(), self.expand_word(&a.value)?)))
+            .collect()
+    }
+
+    /// Runs a simple command. With `exec`, an external command replaces the
+    /// current process instead of running in a child.
+    fn run_simple(&mut self, simple: &SimpleCommand, exec: bool) -> i32 {
+        self.line_number = simple.line;
+        self.run_event_trap(Condition::Debug);
+        self.subst_status = None;
+        let expanded = self
+            .expand_assignments(&simple.assignments)
+            .and_then(|assignments| {
+                let args = self.expand_words(&simple.words)?;
+                let redir = self.expand_redirects(&simple.redirects)?;
+                Ok((assignments, args, redir))
+            });
+        let (assignments, mut args, redir) = match expanded {
+            Ok(expanded) => expanded,
+            Err(err) => {
+                eprintln!("{}", err);
+                return 1;
+            }
+        };
+        if self.options.xtrace {
+            self.trace(&assignments, &args);
+        }
+
+        if args.is_empty() {
+            // A bare redirection such as `> file` still creates or truncates the file
+            let status = self.with_redirects(&redir, |shell| shell.subst_status.unwrap_or(0));
+            if status == 0 || redir.is_empty() {
+                for (name, value) in assignments {
+                    self.vars.set(&name, value);
+                }
+            }
+            return status;
+        }
+
+        let command = args.remove(0);
+        if let Some(body) = self.functions.get(&command).cloned() {
+            return self.with_redirects(&redir, |shell| {
+                shell.with_assignments(assignments, |shell| shell.call_function(&body, args))
+            });
+        }
+        match command.parse::<ShellCommand>().unwrap() {
+            ShellCommand::Exec => self.run_exec(args, assignments, &redir),
+            ShellCommand::Unknown if exec => match self.fds.apply(&redir, &mut self.vars) {
+                // Nothing to restore, as the process is about to be replaced
+                Ok(()) => exec_external(command, args, assignments),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    1
+                }
+            },
+            ShellCommand::Unknown => self.with_redirects(&redir, |shell| {
+                shell.run_external(command, args, assignments)
+            }),
+            builtin => self.with_redirects(&redir, |shell| {
+                shell.with_assignments(assignments, |shell| builtins::run(shell, builtin, args))
+            }),
+        }
+    }
+
+    /// Prints a command about to run for `set -x`: its assignments, then its
+    /// words, each line after `$PS4`.
+    fn trace(&mut self, assignments: &[(String, String)], words: &[String]) {
+        for (name, value) in assignments {
+            self.trace_line(&format!("{}={}", name, quote_word(value)));
+        }
+        if !words.is_empty() {
+            let words: Vec<String> = words.iter().map(|word| quote_word(word)).collect();
+            self.trace_line(&words.join(" "));
+        }
+    }
+
+    /// Prints one line of `set -x` output, after the expanded `$PS4`.
+    pub fn trace_line(&mut self, line: &str) {
+        let ps4 = self.vars.get("PS4").unwrap_or(DEFAULT_PS4).to_string();
+        // Commands in PS4 itself are not traced
+        self.options.xtrace = false;
+        let mut prefix = self.expand_string(&ps4).unwrap_or(ps4);
+        self.options.xtrace = true;
+        if let Some(first) = prefix.chars().next() {
+            let extra = first.to_string().repeat(self.subst_depth);
+            prefix.insert_str(0, &extra);
+        }
+        eprintln!("{}{}", prefix, line);
+    }
+
+    /// Runs `f` with redirections applied, putting the shell's descriptors
+    /// back afterwards. If a redirection fails, `f` is not run.
+    fn with_redirects(&mut self, redirects: &[Redirect], f: impl FnOnce(&mut Shell) -> i32) -> i32 {
+        if redirects.is_empty() {
+            return f(self);
+        }
+        match self.fds.apply(redirects, &mut self.vars) {
+            Ok(()) => {
+                let status = f(self);
+                self.fds.restore();
+                status
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                1
+            }
+        }
+    }
+
+    /// `exec [-cl] [-a name] [command [arg...]]`: replaces the shell with
+    /// an external command, or without one makes the redirections apply to
+    /// the shell from now on.
+    fn run_exec(
+        &mut self,
+        mut args: Vec<String>,
+        assignments: Vec<(String, String)>,
+        redir: &[Redirect],
+    ) -> i32 {
+        let mut clear_env = false;
+        let mut login = false;
+        let mut arg0 = None;
+        while let Some(arg) = args.first().cloned() {
+            match arg.as_str() {
+                "--" => {
+                    args.remove(0);
+                    break;
+                }
+                "-c" => clear_env = true,
+                "-l" => login = true,
+                "-a" if args.len() > 1 => arg0 = Some(args.remove(1)),
+                "-a" => {
+                    eprintln!("exec: -a: option requires an argument");
+                    return 2;
+                }
+                arg if arg.starts_with('-') && arg.len() > 1 => {
+                    eprintln!("exec: {}: invalid option", arg);
+                    eprintln!("exec: usage: exec [-cl] [-a name] [command [argument ...]]");
+                    return 2;
+                }
+                _ => break,
+            }
+            args.remove(0);
+        }
+
+        if let Err(err) = self.fds.apply(redir, &mut self.vars) {
+            eprintln!("{}", err);
+            return 1;
+        }
+        self.fds.keep();
+        if args.is_empty() {
+            for (name, value) in assignments {
+                self.vars.set(&name, value);
+            }
+            return 0;
+        }
+
+        let command = args.remove(0);
+        let Some(mut cmd) = build_external(&command, args, Vec::new()) else {
+            return 127;
+        };
+        if clear_env {
+            cmd.env_clear();
+        }
+        cmd.envs(assignments);
+        let name = arg0.unwrap_or_else(|| command.clone());
+        cmd.arg0(if login { format!("-{}", name) } else { name });
+        let _ = io::stdout().flush();
+        let err = cmd.exec();
+        eprintln!("exec: {}: {}", command, io_error_message(&err));
+        not_executed_status(&err)
+    }
+
+    /// Runs `f` with prefix assignments exported, putting the variables back
+    /// afterwards, as for `VAR=value builtin`.
+    fn with_assignments(
+        &mut self,
+        assignments: Vec<(String, String)>,
+        f: impl FnOnce(&mut Shell) -> i32,
+    ) -> i32 {
+        let saved: Vec<_> = assignments
+            .into_iter()
+            .map(|(name, value)| {
+                let old = self.vars.lookup(&name).cloned();
+                self.vars.export(&name, Some(value));
+                (name, old)
+            })
+            .collect();
+        let status = f(self);
+        for (name, old) in saved {
+            self.vars.restore(&name, old);
+        }
+        status
+    }
+
+    /// Runs a function body in a new frame with `args` as its positional
+    /// parameters.
+    fn call_function(&mut self, body: &ast::Command, args: Vec<String>) -> i32 {
+        let positional = std::mem::replace(&mut self.positional, args);
+        self.frames.push(Frame {
+            positional,
+            locals: Vec::new(),
+        });
+        let return_trap = self.traps.get(Condition::Return).map(str::to_string);
+        // `break` and `continue` can't reach the caller's loops
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let status = self.run_command(body);
+        if self.flow == Some(Flow::Return) {
+            self.flow = None;
+        }
+        self.run_return_trap(return_trap.as_deref(), status);
+
+        let frame = self.frames.pop().unwrap_or_default();
+        for (name, old) in frame.locals.into_iter().rev() {
+            self.vars.restore(&name, old);
+        }
+        self.positional = frame.positional;
+        self.loop_depth = loop_depth;
+        status
+    }
+
+    /// Starts `list` for `<(list)`, or `>(list)` when `output` is set, and
+    /// returns a `/dev/fd` path for the shell's end of the pipe to it.
+    pub fn process_substitution(
+        &mut self,
+        list: &List,
+        output: bool,
+    ) -> Result<String, ExpandError> {
+        let (reader, writer) = os_pipe::pipe().map_err(|e| ExpandError(format!("pipe: {}", e)))?;
+
+        let _ = io::stdout().flush();
+        // SAFETY: the shell is single-threaded, so the child may keep running Rust code.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                self.enter_child();
+                // Holding other substitutions' pipes open would keep their
+                // readers from seeing the end of input
+                for sub in self.process_subs.drain(..) {
+                    if let Some(fd) = sub.fd {
+                        // SAFETY: close only operates on a descriptor number.
+                        unsafe { libc::close(fd) };
+                    }
+                }
+                if output {
+                    drop(writer);
+                    move_fd(reader.as_raw_fd(), 0);
+                    drop(reader);
+                } else {
+                    drop(reader);
+                    move_fd(writer.as_raw_fd(), 1);
+                    drop(writer);
+                }
+                let status = self.run_list(list);
+                self.exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                let end = if output {
+                    drop(reader);
+                    writer.into_raw_fd()
+                } else {
+                    drop(writer);
+                    reader.into_raw_fd()
+                };
+                // A copy without close-on-exec, so commands run with the path
+                // can open it
+                // SAFETY: fcntl and close only operate on descriptor numbers,
+                // and `end` is owned here.
+                let fd = unsafe { libc::fcntl(end, libc::F_DUPFD, SHELL_FD_BASE) };
+                unsafe { libc::close(end) };
+                self.process_subs.push(ProcessSub {
+                    pid: child,
+                    fd: (fd >= 0).then_some(fd),
+                });
+                if fd < 0 {
+                    let err = io::Error::last_os_error();
+                    return Err(ExpandError(io_error_message(&err)));
+                }
+                Ok(format!("/dev/fd/{}", fd))
+            }
+            Err(e) => Err(ExpandError(format!("fork: {}", e))),
+        }
+    }
+
+    /// Closes the shell's ends of the pipes of process substitutions started
+    /// since `mark`, then reaps any that have exited. The rest are reaped on
+    /// a later call, as one may keep running, e.g. after `exec > >(tee log)`.
+    fn finish_process_subs(&mut self, mark: usize) {
+        if self.process_subs.is_empty() {
+            return;
+        }
+        for sub in &mut self.process_subs[mark..] {
+            if let Some(fd) = sub.fd.take() {
+                // SAFETY: close only operates on a descriptor number.
+                unsafe { libc::close(fd) };
+            }
+        }
+        self.process_subs.retain(|sub| {
+            sub.fd.is_some()
+                || matches!(
+                    waitpid(sub.pid, Some(WaitPidFlag::WNOHANG)),
+                    Ok(WaitStatus::StillAlive)
+                )
+        });
+    }
+
+    /// Runs `list` in a forked subshell and returns what it wrote to
+    /// stdout, without trailing newlines.
+    pub fn capture_output(&mut self, list: &List) -> String {
+        let (mut reader, writer) = match os_pipe::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                eprintln!("pipe: {}", e);
+                return String::new();
+            }
+        };
+
+        let _ = io::stdout().flush();
+        // SAFETY: the shell is single-threaded, so the child may keep running Rust code.
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                self.enter_child();
+                self.subst_depth += 1;
+                drop(reader);
+                move_fd(writer.as_raw_fd(), 1);
+                drop(writer);
+                let status = self.run_list(list);
+                self.exit(status);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                drop(writer);
+                let mut output = Vec::new();
+                if let Err(e) = reader.read_to_end(&mut output) {
+                    eprintln!("command substitution: {}", e);
+                }
+                let status = wait_status(waitpid(child, None));
+                self.last_status = status;
+                self.subst_status = Some(status);
+
+                let mut output = String::from_utf8_lossy(&output).into_owned();
+                output.truncate(output.trim_end_matches('\n').len());
+                output
+            }
+            Err(e) => {
+                eprintln!("fork: {}", e);
+                String::new()
+            }
+        }
+    }
+
+    /// Runs a multi-stage pipeline and returns the status of its last stage.
+    fn run_pipe(&mut self, pipeline: &Pipeline) -> i32 {
+        let mut children = Vec::new();
+        // The first stage's process id names the pipeline's process group
+        let mut pgid = None;
+        let mut stdin: Option<PipeReader> = None;
+        let last = pipeline.commands.len() - 1;
+
+        for (i, stage) in pipeline.commands.iter().enumerate() {
+            let (reader, writer) = if i < last {
+                match os_pipe::pipe() {
+                    Ok((reader, writer)) => (Some(reader), Some(writer)),
+                    Err(e) => {
+                        eprintln!("pipe: {}", e);
+                        break;
+                    }
+                }
+            } else {
+                (None, None)
+            };
+
+            let _ = io::stdout().flush();
+            // SAFETY: the shell is single-threaded, so the child may keep running Rust code.
+            match unsafe { fork() } {
+                Ok(ForkResult::Child) => {
+                    if self.job_control {
+                        jobs::setup_child(pgid.map_or(0, Pid::as_raw), true);
+                    }
+                    self.enter_child();
+                    drop(reader);
+                    if let Some(input) = stdin.take() {
+                        move_fd(input.as_raw_fd(), 0);
+                    }
+                    if let Some(output) = writer {
+                        move_fd(output.as_raw_fd(), 1);
+                    }
+                    self.run_stage(stage);
+                }
+                Ok(ForkResult::Parent { child }) => {
+                    let group = *pgid.get_or_insert(child);
+                    if self.job_control {
+                        let _ = unistd::setpgid(child, group);
+                    }
+                    children.push(child);
+                    // The parent keeps only the read end, for the next stage
+                    stdin = reader;
+                }
+                Err(e) => {
+                    eprintln!("fork: {}", e);
+                    break;
+                }
+            }
+        }
+        drop(stdin);
+
+        let Some(pgid) = pgid else {
+            return 1;
+        };
+        self.wait_foreground(Job::new(pgid, children, pipeline.text.clone()))
+    }
+
+    /// Runs an external command as a foreground job.
+    fn run_external(
+        &mut self,
+        command: String,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+    ) -> i32 {
+        let Some(mut cmd) = build_external(&command, args, env) else {
+            return 127;
+        };
+        if self.job_control {
+            // SAFETY: setup_child only makes async-signal-safe calls.
+            unsafe {
+                cmd.pre_exec(|| {
+                    jobs::setup_child(0, true);
+                    Ok(())
+                });
+            }
+        }
+
+        let _ = io::stdout().flush();
+        match cmd.spawn() {
+            Ok(child) => {
+                let pid = Pid::from_raw(child.id() as i32);
+                if self.job_control {
+                    let _ = unistd::setpgid(pid, pid);
+                }
+                let text = self.job_text.clone();
+                self.wait_foreground(Job::new(pid, vec![pid], text))
+            }
+            Err(e) => {
+                eprintln!("{}: failed to execute: {}", command, e);
+                not_executed_status(&e)
+            }
+        }
+    }
+
+    /// Runs one pipeline stage inside its forked child; never returns.
+    fn run_stage(&mut self, stage: &ast::Command) -> ! {
+        let status = match stage {
+            // Already in a child, so an external command can replace it
+            ast::Command::Simple(simple) => self.run_simple(simple, true),
+            _ => self.run_command(stage),
+        };
+        self.exit(status);
+    }
+}
+
+/// Converts the result of `waitpid` into a shell exit status.
+fn wait_status(result: nix::Result<WaitStatus>) -> i32 {
+    match result {
+        Ok(WaitStatus::Exited(_, code)) => code,
+        Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+        _ => 1,
+    }
+}
+
+/// An I/O error worded the way the C library does, without Rust's
+/// "(os error N)" suffix.
+pub fn io_error_message(err: &io::Error) -> String {
+    let message = err.to_string();
+    match err.raw_os_error() {
+        Some(code) => message
+            .strip_suffix(&format!(" (os error {})", code))
+            .unwrap_or(&message)
+            .to_string(),
+        None => message,
+    }
+}
+
+/// Points `target` at the file open on `fd`.
+fn move_fd(fd: RawFd, target: RawFd) {
+    // SAFETY: dup2 only operates on descriptor numbers.
+    if unsafe { libc::dup2(fd, target) } < 0 {
+        eprintln!("dup2: {}", io::Error::last_os_error());
+        process::exit(1);
+    }
+}
+
+/// Replaces the current (forked) process with an external command.
+fn exec_external(command: String, args: Vec<String>, env: Vec<(String, String)>) -> ! {
+    let Some(mut cmd) = build_external(&command, args, env) else {
+        process::exit(127);
+    };
+    let err = cmd.exec();
+    eprintln!("{}: failed to execute: {}", command, err);
+    process::exit(not_executed_status(&err));
+}
+
+/// 127 if the program does not exist, 126 if it could not be run.
+fn not_executed_status(err: &io::Error) -> i32 {
+    if err.kind() == io::ErrorKind::NotFound {
+        127
+    } else {
+        126
+    }
+}
+
+/// Resolves `command` on PATH and sets up its arguments and extra
+/// environment. It inherits the shell's descriptors, redirections included.
+fn build_external(command: &str, args: Vec<String>, env: Vec<(String, String)>) -> Option<Command> {
+    let exec_path = find_exec_function(command);
+
+    if exec_path.is_empty() {
+        eprintln!("{}: command not found", command);
+        return None;
+    }
+
+    let mut cmd = std::process::Command::new(exec_path);
+    cmd.args(args);
+    cmd.envs(env);
+    Some(cmd)
+}
+
+pub fn handle_exec_function(path: &str) -> String {
+    let dir_paths = std::env::var("PATH").unwrap_or_default();
+    let dir_paths = dir_paths.split(':').collect::<Vec<&str>>();
+    let res = String::new();
+    for dir in dir_paths.iter() {
+        let full_path = format!("{}/{}", dir, path);
+        let new_path = std::path::Path::new(&full_path);
+
+        if new_path.exists() {
+            if let Ok(metadata) = fs::metadata(new_path) {
+                let permission = metadata.permissions();
+                let mode = permission.mode();
+                // Check if the file is executable by the user
+                if mode & 0o111 != 0 {
+                    return new_path.display().to_string();
+                };
+            }
+        }
+    }
+
+    res
+}
+
+fn find_exec_function(path: &str) -> String {
+    // A name containing a slash is run as given rather than searched for
+    if path.contains('/') {
+        return path.to_string();
+    }
+
+    let dir_paths = std::env::var("PATH").unwrap_or_default();
+    let dir_paths = dir_paths.split(':').collect::<Vec<&str>>();
+    let res = String::new();
+    for dir in dir_paths.iter() {
+        let full_path = format!("{}/{}", dir, path);
+        let new_path = std::path::Path::new(&full_path);
+
+        if new_path.exists() {
+            if let Ok(metadata) = fs::metadata(new_path) {
+                let permission = metadata.permissions();
+                let mode = permission.mode();
+                // Check if the file is executable by the user
+                if mode & 0o111 != 0 {
+                    return path.to_string();
+                };
+            }
+        }
+    }
+
+    res
+}
+
+/// Quotes a word for output, as by `set -x`, if it has characters the
+/// shell would treat specially, the way bash does.
+pub fn quote_word(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-./:,+=@%^".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Points descriptor 1 at `target` and makes descriptor 2 a copy of it, as
+/// for `&> target`.
+fn push_output_and_error(redirects: &mut Vec<Redirect>, target: String, mode: OpenMode) {
+    redirects.push(Redirect {
+        fd: Fd::Number(1),
+        action: Action::Open { path: target, mode },
+    });
+    redirects.push(Redirect {
+        fd: Fd::Number(2),
+        action: Action::Duplicate(1),
+    });
+}
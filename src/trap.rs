@@ -0,0 +1,224 @@
+//! `trap` actions, for signals and for the shell's own events, and the
+//! queue of caught signals waiting for their actions to run.
+//!
+//! A signal handler can't safely run shell code, so it only marks the
+//! signal as pending; the shell runs the action at the next safe point
+//! between commands.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+use crate::jobs;
+
+/// One more than the highest signal number.
+const NSIG: usize = 65;
+
+/// Caught signals whose actions have not run yet, by number.
+static PENDING: [AtomicBool; NSIG] = [const { AtomicBool::new(false) }; NSIG];
+/// Set whenever any signal is pending, so checking is cheap.
+static ANY_PENDING: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, AtomicOrdering::SeqCst);
+        ANY_PENDING.store(true, AtomicOrdering::SeqCst);
+    }
+}
+
+/// Takes the signals caught since the last call, lowest number first.
+pub fn take_pending() -> Vec<Signal> {
+    if !ANY_PENDING.swap(false, AtomicOrdering::SeqCst) {
+        return Vec::new();
+    }
+    PENDING
+        .iter()
+        .enumerate()
+        .filter(|(_, pending)| pending.swap(false, AtomicOrdering::SeqCst))
+        .filter_map(|(number, _)| Signal::try_from(number as i32).ok())
+        .collect()
+}
+
+/// What a trap is set on: a signal, or one of the shell's own events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// The shell is exiting.
+    Exit,
+    Signal(Signal),
+    /// Before each simple command.
+    Debug,
+    /// A command failed, where `set -e` would exit.
+    Err,
+    /// A function or sourced file is returning.
+    Return,
+}
+
+impl Condition {
+    /// Parses `EXIT`, `DEBUG`, `ERR`, `RETURN` or a signal, by number or by
+    /// name with or without `SIG`, in any case. 0 is `EXIT`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.to_ascii_uppercase().as_str() {
+            "0" | "EXIT" => Some(Condition::Exit),
+            "DEBUG" => Some(Condition::Debug),
+            "ERR" => Some(Condition::Err),
+            "RETURN" => Some(Condition::Return),
+            _ => jobs::parse_signal(spec).map(Condition::Signal),
+        }
+    }
+
+    /// The name `trap -p` shows, such as `EXIT` or `SIGINT`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Condition::Exit => "EXIT",
+            Condition::Signal(signal) => signal.as_str(),
+            Condition::Debug => "DEBUG",
+            Condition::Err => "ERR",
+            Condition::Return => "RETURN",
+        }
+    }
+
+    /// `EXIT` first, then signals by number, then the other events.
+    fn rank(self) -> (u8, i32) {
+        match self {
+            Condition::Exit => (0, 0),
+            Condition::Signal(signal) => (1, signal as i32),
+            Condition::Debug => (2, 0),
+            Condition::Err => (3, 0),
+            Condition::Return => (4, 0),
+        }
+    }
+}
+
+impl Ord for Condition {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+impl PartialOrd for Condition {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The commands set by `trap`. An empty command ignores the signal.
+#[derive(Debug, Default)]
+pub struct Traps {
+    actions: BTreeMap<Condition, String>,
+    /// Signals that were ignored when the shell started, or that a
+    /// background command without job control ignores, which removing a
+    /// trap leaves ignored.
+    ignored: Vec<Signal>,
+}
+
+impl Traps {
+    /// No traps yet, remembering which signals the shell started with
+    /// ignored.
+    pub fn inherit() -> Self {
+        Self {
+            actions: BTreeMap::new(),
+            ignored: Signal::iterator()
+                .filter(|&signal| is_ignored(signal))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, condition: Condition) -> Option<&str> {
+        self.actions.get(&condition).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Condition, &str)> {
+        self.actions
+            .iter()
+            .map(|(condition, action)| (*condition, action.as_str()))
+    }
+
+    /// Sets the command for a condition, catching or ignoring the signal.
+    pub fn set(&mut self, condition: Condition, action: String) {
+        if let Condition::Signal(signal) = condition {
+            let handler = if action.is_empty() {
+                SigHandler::SigIgn
+            } else {
+                SigHandler::Handler(on_signal)
+            };
+            install(signal, handler);
+        }
+        self.actions.insert(condition, action);
+    }
+
+    /// Removes the command for a condition, giving a signal back the
+    /// handling the shell started with.
+    pub fn reset(&mut self, condition: Condition, interactive: bool) {
+        if self.actions.remove(&condition).is_none() {
+            return;
+        }
+        if let Condition::Signal(signal) = condition {
+            let handler = if self.ignored.contains(&signal)
+                || (interactive && jobs::ignored_when_interactive(signal))
+            {
+                SigHandler::SigIgn
+            } else {
+                SigHandler::SigDfl
+            };
+            install(signal, handler);
+        }
+    }
+
+    /// Makes a background command started without job control immune to
+    /// the keyboard signals meant for the foreground, even once its own
+    /// traps on them are removed.
+    pub fn ignore_interrupts(&mut self) {
+        for signal in [Signal::SIGINT, Signal::SIGQUIT] {
+            if !self.ignored.contains(&signal) {
+                self.ignored.push(signal);
+            }
+            install(signal, SigHandler::SigIgn);
+        }
+    }
+
+    /// Removes the traps of a newly forked subshell, which only keeps
+    /// ignoring the signals the shell ignored.
+    pub fn enter_subshell(&mut self) {
+        let conditions: Vec<Condition> = self
+            .actions
+            .iter()
+            .filter(|(_, action)| !action.is_empty())
+            .map(|(condition, _)| *condition)
+            .collect();
+        for condition in conditions {
+            self.reset(condition, false);
+        }
+        for (condition, _) in self.actions.iter() {
+            if let Condition::Signal(signal) = condition {
+                install(*signal, SigHandler::SigIgn);
+            }
+        }
+    }
+
+    /// Removes the command for a condition without touching signal
+    /// handling, as before running the `EXIT` trap.
+    pub fn take(&mut self, condition: Condition) -> Option<String> {
+        self.actions.remove(&condition)
+    }
+}
+
+/// Whether `signal` is ignored right now.
+fn is_ignored(signal: Signal) -> bool {
+    // SAFETY: a null new action only reads the current one.
+    unsafe {
+        let mut old: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(signal as libc::c_int, std::ptr::null(), &mut old) == 0
+            && old.sa_sigaction == libc::SIG_IGN
+    }
+}
+
+fn install(signal: Signal, handler: SigHandler) {
+    // Interrupted reads and waits carry on; the action runs once the
+    // shell gets back between commands
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    // SAFETY: the handler only stores to atomics, which is async-signal-safe.
+    // Failure, as for SIGKILL, leaves the old handling in place.
+    let _ = unsafe { signal::sigaction(signal, &action) };
+}
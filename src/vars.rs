@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+/// The shell's variable table. Exported variables are mirrored into the
+/// process environment so child processes inherit them.
+#[derive(Debug, Default)]
+pub struct Variables {
+    map: HashMap<String, Variable>,
+}
+
+impl Variables {
+    /// Starts from the process environment, with every variable exported.
+    pub fn from_env() -> Self {
+        let map = env::vars()
+            .map(|(name, value)| {
+                let var = Variable {
+                    value,
+                    exported: true,
+                };
+                (name, var)
+            })
+            .collect();
+        Self { map }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name).map(|var| var.value.as_str())
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.map.get(name)
+    }
+
+    /// Assigns a value, keeping the variable's export flag.
+    pub fn set(&mut self, name: &str, value: String) {
+        let exported = self.map.get(name).is_some_and(|var| var.exported);
+        self.insert(name, Variable { value, exported });
+    }
+
+    /// Marks a variable for export, optionally assigning it at the same time.
+    pub fn export(&mut self, name: &str, value: Option<String>) {
+        let value = value
+            .or_else(|| self.get(name).map(str::to_string))
+            .unwrap_or_default();
+        self.insert(
+            name,
+            Variable {
+                value,
+                exported: true,
+            },
+        );
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        if self.map.remove(name).is_some_and(|var| var.exported) {
+            env::remove_var(name);
+        }
+    }
+
+    /// Puts back a variable saved with `lookup`, or removes it if it did not exist.
+    pub fn restore(&mut self, name: &str, saved: Option<Variable>) {
+        match saved {
+            Some(var) => self.insert(name, var),
+            None => self.unset(name),
+        }
+    }
+
+    /// Exported variables, sorted by name.
+    pub fn exported(&self) -> Vec<(&str, &str)> {
+        self.sorted(|var| var.exported)
+    }
+
+    /// All variables, sorted by name.
+    pub fn all(&self) -> Vec<(&str, &str)> {
+        self.sorted(|_| true)
+    }
+
+    fn sorted(&self, pred: impl Fn(&Variable) -> bool) -> Vec<(&str, &str)> {
+        let mut vars: Vec<_> = self
+            .map
+            .iter()
+            .filter(|(_, var)| pred(var))
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+            .collect();
+        vars.sort();
+        vars
+    }
+
+    fn insert(&mut self, name: &str, var: Variable) {
+        if var.exported {
+            env::set_var(name, &var.value);
+        } else if self.map.get(name).is_some_and(|old| old.exported) {
+            env::remove_var(name);
+        }
+        self.map.insert(name.to_string(), var);
+    }
+}
+
+/// Whether `name` can be used as a variable name.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+            chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        }
+        _ => false,
+    }
+}
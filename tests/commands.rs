@@ -0,0 +1,145 @@
+mod common;
+
+use common::{sh, Scratch};
+
+#[test]
+fn pipelines_stream_between_stages() {
+    let run = sh("echo hello | tr a-z A-Z; printf 'b\\na\\nc\\n' | sort | head -1");
+    assert_eq!(run.stdout, "HELLO\na\n");
+    assert_eq!(run.status, 0);
+
+    // Builtins and compound commands take part like any other stage
+    let run = sh("echo x | cat | { echo start; tr x y; }");
+    assert_eq!(run.stdout, "start\ny\n");
+
+    // A stage writing to a closed pipe dies rather than looping forever
+    let run = sh("while true; do echo y; done | head -2");
+    assert_eq!(run.stdout, "y\ny\n");
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn pipeline_status_is_the_last_stage() {
+    assert_eq!(sh("true | false").status, 1);
+    assert_eq!(sh("false | true").status, 0);
+}
+
+#[test]
+fn and_or_lists_and_negation() {
+    let run = sh("false && echo no; true && echo yes; false || echo or; ! false && echo neg");
+    assert_eq!(run.stdout, "yes\nor\nneg\n");
+
+    // `&&` and `||` bind equally, left to right
+    let run = sh("false || false && echo x; echo $?; true || false && echo y");
+    assert_eq!(run.stdout, "1\ny\n");
+
+    let run = sh("! true; echo $?");
+    assert_eq!(run.stdout, "1\n");
+}
+
+#[test]
+fn exit_status_and_exit() {
+    assert_eq!(sh("exit 3").status, 3);
+    assert_eq!(sh("false; exit").status, 1);
+    assert_eq!(sh("exit 300").status, 44);
+    assert_eq!(sh("(exit 4); echo $?").stdout, "4\n");
+
+    let run = sh("nosuchcmd; echo $?");
+    assert_eq!(run.stdout, "127\n");
+    assert!(run.stderr.contains("nosuchcmd: command not found"));
+
+    let run = sh("exit abc; echo not reached");
+    assert_eq!(run.stdout, "");
+    assert_eq!(run.status, 2);
+}
+
+#[test]
+fn if_and_loops() {
+    let run = sh("for x in a b; do \
+            if [ $x = a ]; then echo A; elif false; then :; else echo other $x; fi; \
+        done");
+    assert_eq!(run.stdout, "A\nother b\n");
+
+    let run = sh(
+        "i=0; while [ $i -lt 3 ]; do i=$((i + 1)); [ $i = 2 ] && continue; echo $i; done; \
+        until true; do echo never; done; echo $?",
+    );
+    assert_eq!(run.stdout, "1\n3\n0\n");
+
+    let run = sh("for i in 1 2; do for j in 1 2; do [ $j = 2 ] && break 2; echo $i$j; done; done");
+    assert_eq!(run.stdout, "11\n");
+
+    // Without `in`, `for` loops over the positional parameters
+    let run = sh("set -- p q; for a; do echo $a; done");
+    assert_eq!(run.stdout, "p\nq\n");
+}
+
+#[test]
+fn case_patterns() {
+    let run = sh("for v in apple b.txt Z 7; do case $v in \
+            a*|x) echo fruit;; \
+            *.txt) echo text;& \
+            [[:upper:]]) echo upper;; \
+            [0-9]) echo digit;;& \
+            *) echo any;; \
+        esac; done");
+    assert_eq!(run.stdout, "fruit\ntext\nupper\nupper\ndigit\nany\n");
+
+    // A quoted pattern matches literally
+    let run = sh("case 'a*' in \"a*\") echo literal;; esac; case ab in 'a*') echo wrong;; esac");
+    assert_eq!(run.stdout, "literal\n");
+}
+
+#[test]
+fn functions() {
+    let run = sh("x=global; \
+        f() { local x=inner; echo \"$1 $# $x\"; g; return 3; echo no; }; \
+        g() { echo \"g sees $x\"; }; \
+        f a b; echo \"$? $x [$1]\"");
+    assert_eq!(run.stdout, "a 2 inner\ng sees inner\n3 global []\n");
+
+    let run = sh("function fact { \
+            if [ $1 -le 1 ]; then echo 1; else echo $(( $1 * $(fact $(($1 - 1))) )); fi; \
+        }; fact 5");
+    assert_eq!(run.stdout, "120\n");
+}
+
+#[test]
+fn break_in_a_function_does_not_leave_the_callers_loop() {
+    let run = sh("f() { break; }; for i in 1 2; do f; echo $i; done");
+    assert_eq!(run.stdout, "1\n2\n");
+    assert!(run
+        .stderr
+        .contains("break: only meaningful in a `for', `while', or `until' loop"));
+
+    // Loops inside the function still work
+    let run =
+        sh("f() { for j in a b; do continue; echo no; done; echo f; }; for i in 1; do f; done");
+    assert_eq!(run.stdout, "f\n");
+}
+
+#[test]
+fn source_and_eval() {
+    let dir = Scratch::new("source");
+    dir.write(
+        "lib.sh",
+        "echo \"sourced $1 $#\"\nv=set\nreturn 4\necho no\n",
+    );
+    let run = dir.sh(". ./lib.sh a b; echo \"$? $v\"; source ./lib.sh; . ./missing.sh; echo $?");
+    assert_eq!(run.stdout, "sourced a 2\n4 set\nsourced  0\n1\n");
+    assert!(run
+        .stderr
+        .contains("./missing.sh: No such file or directory"));
+
+    let run = sh("eval 'x=1; echo $x' more; cmd='echo evaluated'; eval $cmd; eval false; echo $?");
+    assert_eq!(run.stdout, "1 more\nevaluated\n1\n");
+}
+
+#[test]
+fn aliases() {
+    let run = sh("alias ll='echo long' e='echo ' w=world\nll x\ne w\nunalias ll\nll 2>/dev/null || echo gone\nalias");
+    assert_eq!(
+        run.stdout,
+        "long x\nworld\ngone\nalias e='echo '\nalias w='world'\n"
+    );
+}
@@ -0,0 +1,78 @@
+//! Runs the built shell for the integration tests.
+
+// Each test binary uses only some of these helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// What a run of the shell printed, and how it exited.
+#[derive(Debug)]
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    /// The exit status, or 128 plus the signal that killed the shell.
+    pub status: i32,
+}
+
+/// The shell, reading nothing from stdin and no `$ENV` file.
+pub fn shell() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"));
+    command.stdin(Stdio::null()).env_remove("ENV");
+    command
+}
+
+pub fn run(command: &mut Command) -> Run {
+    let output = command.output().expect("failed to run the shell");
+    let status = output.status.code().unwrap_or_else(|| {
+        use std::os::unix::process::ExitStatusExt;
+        128 + output.status.signal().unwrap_or(0)
+    });
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status,
+    }
+}
+
+/// Runs `script` with `-c`.
+pub fn sh(script: &str) -> Run {
+    run(shell().arg("-c").arg(script))
+}
+
+/// A directory of its own for a test, removed again on drop.
+pub struct Scratch {
+    pub path: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("rsh-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Writes a file, creating directories as needed.
+    pub fn write(&self, name: &str, contents: &str) {
+        let path = self.path.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.path.join(name)).unwrap_or_default()
+    }
+
+    /// Runs `script` with `-c` inside the directory.
+    pub fn sh(&self, script: &str) -> Run {
+        run(shell().current_dir(&self.path).arg("-c").arg(script))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
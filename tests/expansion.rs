@@ -0,0 +1,130 @@
+mod common;
+
+use common::{sh, Scratch};
+
+#[test]
+fn variables_and_export() {
+    let run = sh("x=hello; echo $x ${x}world \"$x\"");
+    assert_eq!(run.stdout, "hello helloworld hello\n");
+
+    // Exported variables and prefix assignments reach child processes,
+    // and a prefix assignment doesn't outlive its command
+    let run = sh("export Y=1; sh -c 'echo $Y'; Z=2 sh -c 'echo $Z'; echo \"[$Z]\"");
+    assert_eq!(run.stdout, "1\n2\n[]\n");
+}
+
+#[test]
+fn positional_and_special_parameters() {
+    let run = sh("set -- a 'b c'; echo $#; for a in \"$@\"; do echo \"<$a>\"; done; echo \"$*\"");
+    assert_eq!(run.stdout, "2\n<a>\n<b c>\na b c\n");
+
+    let run = run_with_args(&["-c", "echo $0 $1 $2", "name", "one", "two"]);
+    assert_eq!(run.stdout, "name one two\n");
+
+    let run = sh("false; echo $?; x=$$; (echo $(( $$ == x )))");
+    assert_eq!(run.stdout, "1\n1\n");
+}
+
+fn run_with_args(args: &[&str]) -> common::Run {
+    common::run(common::shell().args(args))
+}
+
+#[test]
+fn parameter_operators() {
+    let run = sh("v=a.b.c; echo ${v#*.} ${v##*.} ${v%.*} ${v%%.*} ${#v}");
+    assert_eq!(run.stdout, "b.c c a.b a 5\n");
+    let run = sh("v=a.b.c; echo ${v/./-} ${v//./-} ${v:1:3} ${v: -1} ${v^^}");
+    assert_eq!(run.stdout, "a-b.c a-b-c .b. c A.B.C\n");
+    let run = sh("e=; v=1; echo ${u:-def} ${e:-null} ${e-set} ${v:+alt}; echo ${w:=new} $w");
+    assert_eq!(run.stdout, "def null alt\nnew new\n");
+}
+
+#[test]
+fn unset_parameter_error_ends_a_script() {
+    let run = sh("echo first\necho ${u:?oops}; echo after");
+    assert_eq!(run.stdout, "first\n");
+    assert!(run.stderr.ends_with(": -c: line 2: u: oops\n"));
+    assert_eq!(run.status, 127);
+
+    // A subshell only ends itself
+    let run = sh("(echo ${u:?}); echo after $?");
+    assert_eq!(run.stdout, "after 127\n");
+}
+
+#[test]
+fn fatal_errors_report_the_script_line() {
+    let dir = Scratch::new("fatal-line");
+    dir.write(
+        "s.sh",
+        "f() {\n  echo ${v:?missing}\n}\necho start\nf\necho after\n",
+    );
+    let run = common::run(common::shell().current_dir(&dir.path).arg("s.sh"));
+    assert_eq!(run.stdout, "start\n");
+    assert_eq!(run.stderr, "s.sh: line 2: v: missing\n");
+    assert_eq!(run.status, 127);
+}
+
+#[test]
+fn negative_substring_length() {
+    let run = sh("x=abcdef; echo ${x:1:-4}; echo ${x:7:-5}.; echo ${x:1:-6}; echo after");
+    assert_eq!(run.stdout, "b\n.\n");
+    assert!(run.stderr.contains("-6: substring expression < 0"));
+}
+
+#[test]
+fn command_substitution() {
+    let run = sh("echo $(echo a; echo b) `echo c`");
+    assert_eq!(run.stdout, "a b c\n");
+
+    // Trailing newlines are removed, and substitutions nest
+    let run = sh("x=$(printf 'x\\n\\n'); echo \"[$x]\"; echo \"$(echo \"nested $(echo in)\")\"");
+    assert_eq!(run.stdout, "[x]\nnested in\n");
+
+    // A bare assignment takes the substitution's status
+    let run = sh("x=$(exit 3); echo $?");
+    assert_eq!(run.stdout, "3\n");
+}
+
+#[test]
+fn pathname_globbing() {
+    let dir = Scratch::new("globbing");
+    for name in ["b.txt", "a.txt", ".hidden.txt", "c.rs", "sub/d.txt"] {
+        dir.write(name, "");
+    }
+    let run = dir.sh("echo *.txt; echo ?.rs */*.txt; echo '*.txt' \\*.txt; echo *.none");
+    assert_eq!(
+        run.stdout,
+        "a.txt b.txt\nc.rs sub/d.txt\n*.txt *.txt\n*.none\n"
+    );
+
+    let run = dir.sh("shopt -s nullglob; echo \"<\" *.none \">\"; shopt -s dotglob; echo *.txt");
+    assert_eq!(run.stdout, "< >\n.hidden.txt a.txt b.txt\n");
+
+    let run = dir.sh("shopt -s failglob; echo *.none; echo after");
+    assert_eq!(run.stdout, "");
+    assert!(run.stderr.ends_with(": -c: line 1: no match: *.none\n"));
+    assert_eq!(run.status, 1);
+}
+
+#[test]
+fn brace_expansion() {
+    let run = sh("echo {a,b}{1,2} x{1..3} {c..a} {1..10..4} {08..10}");
+    assert_eq!(run.stdout, "a1 a2 b1 b2 x1 x2 x3 c b a 1 5 9 08 09 10\n");
+
+    // Quoted braces and ones that don't form a list are left alone
+    let run = sh("echo \"{a,b}\" {a} {1..a}");
+    assert_eq!(run.stdout, "{a,b} {a} {1..a}\n");
+}
+
+#[test]
+fn arithmetic() {
+    let run = sh("echo $((2 + 3 * 4)) $((x = 5, x * 2)) $x; ((x > 3)) && echo big; ((0)); echo $?");
+    assert_eq!(run.stdout, "14 10 5\nbig\n1\n");
+
+    // A failed `((...))` command is just a false one, but a failed
+    // expansion ends the script
+    let run = sh("((1 / 0)); echo status $?; echo $((1 / 0)); echo after");
+    assert_eq!(run.stdout, "status 1\n");
+    assert!(run.stderr.contains(": -c: line 1: 1 / 0: division by 0"));
+    assert_eq!(run.status, 1);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::io::Write;
+use std::process::Stdio;
+
+use common::{sh, shell, Scratch};
+
+#[test]
+fn script_file_with_arguments() {
+    let dir = Scratch::new("script-file");
+    dir.write("s.sh", "echo \"$0 $# $1 $2\"\nexit 7\n");
+    let script = dir.path.join("s.sh");
+    let run = common::run(shell().arg(&script).args(["a", "b c"]));
+    assert_eq!(run.stdout, format!("{} 2 a b c\n", script.display()));
+    assert_eq!(run.status, 7);
+
+    let run = common::run(shell().arg(dir.path.join("missing.sh")));
+    assert_eq!(run.status, 127);
+}
+
+#[test]
+fn command_string() {
+    let run = common::run(shell().args(["-c", "echo $0 $@; exit 4", "name", "x", "y"]));
+    assert_eq!(run.stdout, "name x y\n");
+    assert_eq!(run.status, 4);
+
+    let run = common::run(shell().arg("-c"));
+    assert_eq!(run.status, 2);
+    assert!(sh("if").stderr.contains("syntax error"));
+    assert_eq!(sh("if").status, 2);
+}
+
+#[test]
+fn script_from_stdin() {
+    let mut child = shell()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"echo one\nif true; then\n  echo two\nfi\nexit 5\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\n");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn flags_combine_with_c() {
+    let run = common::run(shell().args(["-ec", "false; echo not reached"]));
+    assert_eq!(run.stdout, "");
+    assert_eq!(run.status, 1);
+
+    let run = common::run(shell().args(["-xc", "echo ok"]));
+    assert_eq!(run.stdout, "ok\n");
+    assert_eq!(run.stderr, "+ echo ok\n");
+
+    let run = common::run(shell().args(["-q", "-c", "echo ok"]));
+    assert_eq!(run.status, 2);
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::sh;
+
+#[test]
+fn background_jobs_and_wait() {
+    let run = sh("(exit 3) & wait $!; echo $?; sleep 5 & jobs; kill %1; wait %1; echo $?");
+    assert_eq!(
+        run.stdout,
+        "3\n[1]+  Running                 sleep 5 &\n143\n"
+    );
+
+    let run = sh("sleep 5 & disown; jobs; echo disowned; kill $!");
+    assert_eq!(run.stdout, "disowned\n");
+
+    let run = sh("wait 1; echo $?; fg; echo $?");
+    assert_eq!(run.stdout, "127\n1\n");
+    assert!(run
+        .stderr
+        .contains("wait: pid 1 is not a child of this shell"));
+    assert!(run.stderr.contains("fg: no job control"));
+}
+
+#[test]
+fn signals_between_the_shell_and_its_children() {
+    // Without job control, background jobs ignore keyboard interrupts
+    let run = sh("sleep 5 & kill -INT $!; sleep 0.2; kill -0 $! && echo alive; kill $!");
+    assert_eq!(run.stdout, "alive\n");
+
+    // A command killed by a signal has status 128 plus its number
+    let run = sh("sh -c 'kill -TERM $$'; echo $?; sh -c 'kill -INT $$'; echo $?");
+    assert_eq!(run.stdout, "143\n130\n");
+    assert!(run.stderr.contains("Terminated"));
+}
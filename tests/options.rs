@@ -0,0 +1,72 @@
+mod common;
+
+use common::{sh, Scratch};
+
+#[test]
+fn errexit() {
+    let run =
+        sh("set -e; false || echo or; if false; then :; fi; ! true; echo still; false; echo no");
+    assert_eq!((run.stdout.as_str(), run.status), ("or\nstill\n", 1));
+}
+
+#[test]
+fn nounset() {
+    let run = sh("set -u; echo ${unset-def}; echo $unset; echo no");
+    assert_eq!(run.stdout, "def\n");
+    assert!(run
+        .stderr
+        .ends_with(": -c: line 1: unset: unbound variable\n"));
+    assert_eq!(run.status, 127);
+}
+
+#[test]
+fn xtrace() {
+    let run = sh("PS4='+> '; set -x; echo a $((1+1)); set +x; echo b");
+    assert_eq!(run.stdout, "a 2\nb\n");
+    assert_eq!(run.stderr, "+> echo a 2\n+> set +x\n");
+}
+
+#[test]
+fn pipefail() {
+    let run = sh("false | true; echo $?; set -o pipefail; false | true; echo $?");
+    assert_eq!(run.stdout, "0\n1\n");
+}
+
+#[test]
+fn noclobber() {
+    let dir = Scratch::new("noclobber");
+    dir.write("f", "old\n");
+
+    let run = dir.sh("set -C; echo new > f; echo $?; cat f; echo forced >| f; cat f");
+    assert_eq!(run.stdout, "1\nold\nforced\n");
+    assert!(run.stderr.contains("f: cannot overwrite existing file"));
+
+    // Only regular files are protected
+    let run = dir.sh("set -C; echo gone > /dev/null && echo ok; echo made > g; cat g");
+    assert_eq!(run.stdout, "ok\nmade\n");
+    let run = dir.sh("mkfifo p; cat p & set -C; echo piped > p; wait");
+    assert_eq!(run.stdout, "piped\n");
+}
+
+#[test]
+fn noglob_and_noexec() {
+    let dir = Scratch::new("noglob");
+    dir.write("a.txt", "");
+    let run = dir.sh("set -f; echo *.txt; set +f; echo *.txt");
+    assert_eq!(run.stdout, "*.txt\na.txt\n");
+
+    let run = sh("echo before; set -n; echo after");
+    assert_eq!((run.stdout.as_str(), run.status), ("before\n", 0));
+}
+
+#[test]
+fn listing_options() {
+    let run = sh("set -eu; echo $-; set +eu; echo \"[$-]\"");
+    assert_eq!(run.stdout, "eu\n[]\n");
+
+    let run = sh("set -o | grep -E 'errexit|noclobber'; set -C; set +o | grep noclobber");
+    assert_eq!(
+        run.stdout,
+        "errexit        \toff\nnoclobber      \toff\nset -o noclobber\n"
+    );
+}
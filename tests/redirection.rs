@@ -0,0 +1,62 @@
+mod common;
+
+use common::{sh, Scratch};
+
+#[test]
+fn here_documents_and_strings() {
+    let run = sh("x=var\ncat <<EOF\nhello $x $(echo sub)\n\\$x\nEOF\ncat <<'Q'\n$x\nQ\n");
+    assert_eq!(run.stdout, "hello var sub\n$x\n$x\n");
+
+    let run = sh("\tcat <<-T\n\t\ttabs\n\tT\ncat <<A; cat <<B\none\nA\ntwo\nB\n");
+    assert_eq!(run.stdout, "tabs\none\ntwo\n");
+
+    let run = sh("x=var; cat <<< \"$x here\"; tr a-z A-Z <<<abc");
+    assert_eq!(run.stdout, "var here\nABC\n");
+}
+
+#[test]
+fn duplicating_and_closing_descriptors() {
+    let dir = Scratch::new("duplicate");
+    let both = "{ echo out; echo err >&2; }";
+
+    // Redirections apply left to right
+    let run = dir.sh(&format!("{} 2>&1 >/dev/null", both));
+    assert_eq!((run.stdout.as_str(), run.stderr.as_str()), ("err\n", ""));
+    let run = dir.sh(&format!(
+        "{} >f 2>&1; cat f; {} &>g; echo more &>>g; cat g",
+        both, both
+    ));
+    assert_eq!(run.stdout, "out\nerr\nout\nerr\nmore\n");
+
+    let run = dir.sh("{ echo a; echo b >&2; } 2>&1 | tr a-z A-Z");
+    assert_eq!(run.stdout, "A\nB\n");
+
+    let run = dir.sh("echo data >rw; cat 4<>rw <&4; echo x 3>&- >&3");
+    assert_eq!(run.stdout, "data\n");
+    assert!(run.stderr.contains("3: Bad file descriptor"));
+}
+
+#[test]
+fn exec_redirections_persist() {
+    let dir = Scratch::new("exec");
+    let run = dir.sh("exec 3>h; echo three >&3; exec 3>&-; cat h; echo x >&3");
+    assert_eq!(run.stdout, "three\n");
+    assert!(run.stderr.contains("3: Bad file descriptor"));
+
+    let run =
+        dir.sh("exec {fd}>k; echo named >&$fd; exec {fd}>&-; cat k; [ $fd -ge 10 ] && echo high");
+    assert_eq!(run.stdout, "named\nhigh\n");
+
+    // `exec` with a command replaces the shell
+    let run = dir.sh("exec echo replaced; echo not reached");
+    assert_eq!(run.stdout, "replaced\n");
+}
+
+#[test]
+fn process_substitution() {
+    let run = sh("cat <(echo in); diff <(echo a) <(echo a) && echo same");
+    assert_eq!(run.stdout, "in\nsame\n");
+
+    let run = sh("echo x > >(tr x y); wait");
+    assert_eq!(run.stdout, "y\n");
+}
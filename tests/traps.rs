@@ -0,0 +1,46 @@
+mod common;
+
+use common::sh;
+
+#[test]
+fn exit_and_signal_traps() {
+    let run = sh(
+        "trap 'echo bye $?' EXIT; trap 'echo usr1' USR1; kill -USR1 $$; echo after; \
+        trap -p USR1; (echo subshell); exit 3",
+    );
+    assert_eq!(
+        run.stdout,
+        "usr1\nafter\ntrap -- 'echo usr1' SIGUSR1\nsubshell\nbye 3\n"
+    );
+    assert_eq!(run.status, 3);
+
+    // An ignored signal stays ignored in children
+    let run = sh("trap '' USR1; sh -c 'kill -USR1 $$; echo survived'");
+    assert_eq!(run.stdout, "survived\n");
+}
+
+#[test]
+fn err_and_debug_traps() {
+    let run = sh(
+        "f() { false; }; trap 'echo err' ERR; f; false || true; if false; then :; fi; \
+        trap - ERR; false; trap 'echo dbg' DEBUG; echo cmd",
+    );
+    assert_eq!(run.stdout, "err\ndbg\ncmd\n");
+}
+
+#[test]
+fn background_jobs_ignore_interrupts_despite_traps() {
+    let run = sh(
+        "trap 'echo caught' INT; sleep 5 & kill -INT $!; sleep 0.2; \
+        kill -0 $! && echo alive; kill $!",
+    );
+    assert_eq!(run.stdout, "alive\n");
+
+    // Removing a trap on a signal ignored when the shell started leaves
+    // it ignored
+    let script = format!(
+        "trap '' INT; {} -c \"trap 'echo caught' INT; trap - INT; kill -INT \\$\\$; echo survived\"",
+        env!("CARGO_BIN_EXE_codecrafters-shell")
+    );
+    assert_eq!(sh(&script).stdout, "survived\n");
+}